@@ -0,0 +1,60 @@
+/* eslint-disable */
+/* tslint:disable */
+/* Generated by Specta for Juno. DO NOT EDIT */
+import { z } from 'zod';
+
+export const AddNumbersInputSchema = z.object({ first: z.number().int(), second: z.number().int() });
+
+export const DeleteUserErrorSchema = z.union([z.literal("LastAdmin"), z.object({ HasOpenInvoices: z.object({ count: z.number().int().nonnegative() }) })]);
+
+export const DeleteUserInputSchema = z.object({ user_id: z.number().int().nonnegative() });
+
+export const ErrorDataSchema = z.object({ path: z.string() }).and(z.object({ code: z.string(), httpStatus: z.number().int().nonnegative() }));
+
+export const GetHeaderInputSchema = z.object({ name: z.string() });
+
+export const GetUserInputSchema = z.object({ user_id: z.number().int().nonnegative() });
+
+export const GuildSchema = z.object({ id: z.number().int().nonnegative(), name: z.string(), users: z.array(z.lazy(() => UserSchema)) });
+
+export const InviteUserInputSchema = z.object({ email: z.string().email().describe("Must be an email address"), seats: z.number().int().nonnegative().gte(1).lte(10).nullable().describe("Must be between 1 and 10") });
+
+/**
+ * An object key or array index in the path of a [`ValidationIssue`].
+ */
+export const PathSegmentSchema = z.union([z.string(), z.number().int().nonnegative()]);
+
+export const UpdateNickInputSchema = z.object({ user_id: z.number().int().nonnegative(), nick: z.string().min(1).max(32).describe("Length must be between 1 and 32") });
+
+export const UploadedFileSchema = z.object({ name: z.string(), size: z.number().int().nonnegative() });
+
+export const UserSchema = z.object({ id: z.number().int().nonnegative(), name: z.string(), nick: z.string().nullable() });
+
+/**
+ * A problem with one field of a procedure's input, sent to the client in `error.data.issues`
+ * like the issues of a zod error.
+ */
+export const ValidationIssueSchema = z.object({ path: z.array(z.lazy(() => PathSegmentSchema)).describe("The path to the field in the input, e.g. `[\"address\", \"lines\", 0]`. Empty when the\n input as a whole is invalid."), expected: z.string().nullable().describe("The type or value the field should have, when it is known."), message: z.string() });
+
+export const WatchServerTimeInputSchema = z.object({ interval_ms: z.number().int().nonnegative() });
+
+export const procedures = {
+    add_numbers: { input: z.lazy(() => AddNumbersInputSchema), output: z.number().int() },
+    create_session: { input: z.undefined(), output: z.string() },
+    delete_user: { input: z.lazy(() => DeleteUserInputSchema), output: z.null() },
+    "files.export_users": { input: z.object({ count: z.number().int().nonnegative() }), output: z.custom<ReadableStream<Uint8Array>>() },
+    "files.upload_avatar": { input: z.custom<Blob | Uint8Array>(), output: z.number().int().nonnegative() },
+    "files.upload_files": { input: z.custom<FormData>(), output: z.array(z.lazy(() => UploadedFileSchema)) },
+    get_api_version: { input: z.undefined(), output: z.string() },
+    get_guild: { input: z.number().int().nonnegative().gte(1), output: z.lazy(() => GuildSchema) },
+    get_header: { input: z.lazy(() => GetHeaderInputSchema), output: z.string().nullable() },
+    get_me: { input: z.undefined(), output: z.lazy(() => UserSchema) },
+    get_server_time: { input: z.undefined(), output: z.string() },
+    get_user: { input: z.lazy(() => GetUserInputSchema), output: z.lazy(() => UserSchema) },
+    get_user_agent: { input: z.undefined(), output: z.string().nullable() },
+    invite_user: { input: z.lazy(() => InviteUserInputSchema), output: z.null() },
+    no_output: { input: z.undefined(), output: z.null() },
+    update_nick: { input: z.lazy(() => UpdateNickInputSchema), output: z.null() },
+    watch_counter: { input: z.undefined(), output: z.object({ id: z.string(), data: z.number().int().nonnegative() }) },
+    watch_server_time: { input: z.lazy(() => WatchServerTimeInputSchema), output: z.string() }
+};
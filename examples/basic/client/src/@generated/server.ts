@@ -1,22 +1,91 @@
 /* eslint-disable */
 /* tslint:disable */
 /* Generated by Specta for Juno. DO NOT EDIT */
-import { initTRPC } from '@trpc/server';
+import { initTRPC, type TrackedEnvelope } from '@trpc/server';
 
 export type AddNumbersInput = { first: number; second: number }
 
+export type DeleteUserError = "LastAdmin" | { HasOpenInvoices: { count: number } }
+
+export type DeleteUserInput = { user_id: number }
+
+export type ErrorData = ({ code: string; httpStatus: number }) & { path: string }
+
+export type GetHeaderInput = { name: string }
+
 export type GetUserInput = { user_id: number }
 
+export type Guild = { id: number; name: string; users: User[] }
+
+export type InviteUserInput = { 
+/**
+ * Must be an email address
+ */
+email: string; 
+/**
+ * Must be between 1 and 10
+ */
+seats: number | null }
+
+/**
+ * An object key or array index in the path of a [`ValidationIssue`].
+ */
+export type PathSegment = string | number
+
+export type UpdateNickInput = { user_id: number; 
+/**
+ * Length must be between 1 and 32
+ */
+nick: string }
+
+export type UploadedFile = { name: string; size: number }
+
 export type User = { id: number; name: string; nick: string | null }
 
-const t = initTRPC.create();
+/**
+ * A problem with one field of a procedure's input, sent to the client in `error.data.issues`
+ * like the issues of a zod error.
+ */
+export type ValidationIssue = { 
+/**
+ * The path to the field in the input, e.g. `["address", "lines", 0]`. Empty when the
+ * input as a whole is invalid.
+ */
+path: PathSegment[]; 
+/**
+ * The type or value the field should have, when it is known.
+ */
+expected: string | null; message: string }
+
+export type WatchServerTimeInput = { interval_ms: number }
+
+export type ErrorDetails = DeleteUserError;
+
+const t = initTRPC.create({ errorFormatter: ({ shape }) => ({ ...shape, data: shape.data as unknown as ErrorData & { issues?: ValidationIssue[]; details?: ErrorDetails } }) });
 const publicProcedure = t.procedure;
+const protectedProcedure = t.procedure;
 const appRouter = t.router({
     add_numbers: publicProcedure.input((value): AddNumbersInput => { throw new Error('Router should not be used') }).output((value): number => { throw new Error('Router should not be used') }).mutation((opts): number => { throw new Error('Router should not be used') }),
-get_user: publicProcedure.input((value): GetUserInput => { throw new Error('Router should not be used') }).output((value): User => { throw new Error('Router should not be used') }).query((opts): User => { throw new Error('Router should not be used') }),
-get_server_time: publicProcedure.output((value): string => { throw new Error('Router should not be used') }).query((opts): string => { throw new Error('Router should not be used') }),
+create_session: publicProcedure.output((value): string => { throw new Error('Router should not be used') }).mutation((opts): string => { throw new Error('Router should not be used') }),
+/** Requires `admin` */
+delete_user: protectedProcedure.input((value): DeleteUserInput => { throw new Error('Router should not be used') }).mutation((opts): void => { throw new Error('Router should not be used') }),
+files: t.router({
+    export_users: publicProcedure.input((value): { count: number } => { throw new Error('Router should not be used') }).output((value): ReadableStream<Uint8Array> => { throw new Error('Router should not be used') }).query((opts): ReadableStream<Uint8Array> => { throw new Error('Router should not be used') }),
+    upload_avatar: publicProcedure.input((value): Blob | Uint8Array => { throw new Error('Router should not be used') }).output((value): number => { throw new Error('Router should not be used') }).mutation((opts): number => { throw new Error('Router should not be used') }),
+    upload_files: publicProcedure.input((value): FormData => { throw new Error('Router should not be used') }).output((value): UploadedFile[] => { throw new Error('Router should not be used') }).mutation((opts): UploadedFile[] => { throw new Error('Router should not be used') })
+}),
 get_api_version: publicProcedure.output((value): string => { throw new Error('Router should not be used') }).query((opts): string => { throw new Error('Router should not be used') }),
-no_output: publicProcedure.query((opts): void => { throw new Error('Router should not be used') })
+get_guild: publicProcedure.input((value): number => { throw new Error('Router should not be used') }).output((value): Guild => { throw new Error('Router should not be used') }).query((opts): Guild => { throw new Error('Router should not be used') }),
+get_header: publicProcedure.input((value): GetHeaderInput => { throw new Error('Router should not be used') }).output((value): string | null => { throw new Error('Router should not be used') }).query((opts): string | null => { throw new Error('Router should not be used') }),
+get_me: protectedProcedure.output((value): User => { throw new Error('Router should not be used') }).query((opts): User => { throw new Error('Router should not be used') }),
+get_server_time: publicProcedure.output((value): string => { throw new Error('Router should not be used') }).query((opts): string => { throw new Error('Router should not be used') }),
+get_user: publicProcedure.input((value): GetUserInput => { throw new Error('Router should not be used') }).output((value): User => { throw new Error('Router should not be used') }).query((opts): User => { throw new Error('Router should not be used') }),
+get_user_agent: publicProcedure.output((value): string | null => { throw new Error('Router should not be used') }).query((opts): string | null => { throw new Error('Router should not be used') }),
+invite_user: publicProcedure.input((value): InviteUserInput => { throw new Error('Router should not be used') }).mutation((opts): void => { throw new Error('Router should not be used') }),
+no_output: publicProcedure.query((opts): void => { throw new Error('Router should not be used') }),
+update_nick: protectedProcedure.input((value): UpdateNickInput => { throw new Error('Router should not be used') }).mutation((opts): void => { throw new Error('Router should not be used') }),
+watch_counter: publicProcedure.subscription((opts): AsyncGenerator<TrackedEnvelope<number>> => { throw new Error('Router should not be used') }),
+watch_server_time: publicProcedure.input((value): WatchServerTimeInput => { throw new Error('Router should not be used') }).subscription((opts): AsyncGenerator<string> => { throw new Error('Router should not be used') })
 });
 
 export type AppRouter = typeof appRouter;
@@ -1,10 +1,30 @@
-import { createTRPCClient, httpLink } from "@trpc/client";
+import {
+	createTRPCClient,
+	httpBatchLink,
+	httpLink,
+	httpSubscriptionLink,
+	isNonJsonSerializable,
+	splitLink,
+} from "@trpc/client";
 import type { AppRouter } from "./@generated/server";
 
 const client = createTRPCClient<AppRouter>({
 	links: [
-		httpLink({
-			url: "http://localhost:3000/trpc",
+		splitLink({
+			condition: (op) => op.type === "subscription",
+			true: httpSubscriptionLink({
+				url: "http://localhost:3000/trpc",
+			}),
+			false: splitLink({
+				// FormData can't be batched, so it gets sent on its own
+				condition: (op) => isNonJsonSerializable(op.input),
+				true: httpLink({
+					url: "http://localhost:3000/trpc",
+				}),
+				false: httpBatchLink({
+					url: "http://localhost:3000/trpc",
+				}),
+			}),
 		}),
 	],
 });
@@ -22,3 +42,24 @@ const sum = await client.add_numbers.mutate({ first: 1, second: 2 });
 console.log({ sum });
 
 await client.no_output.query();
+
+const form = new FormData();
+form.append("file", new File(["hello world"], "hello.txt", { type: "text/plain" }));
+const uploaded = await client.files.upload_files.mutate(form);
+console.log({ uploaded });
+
+const avatarSize = await client.files.upload_avatar.mutate(new Blob(["not really a png"]));
+console.log({ avatarSize });
+
+// Binary results aren't JSON, so they're fetched directly rather than through a link
+const input = encodeURIComponent(JSON.stringify({ count: 3 }));
+const csv = await fetch(`http://localhost:3000/trpc/files.export_users?input=${input}`);
+console.log({ csv: await csv.text() });
+
+const subscription = client.watch_server_time.subscribe(
+	{ interval_ms: 500 },
+	{
+		onData: (time) => console.log({ time }),
+	},
+);
+setTimeout(() => subscription.unsubscribe(), 2000);
@@ -1,13 +1,47 @@
-use axum::{Router, extract::State};
-use juno::errors::{RpcError, RpcStatus};
-use juno::router::RpcRouter;
+use axum::{
+    Router,
+    extract::{FromRef, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+};
+use futures_util::{Stream, stream};
+use juno::auth::{BearerAuth, Decision, Identity, PermissionResolver, Policy};
+use juno::context::Ctx;
+use juno::errors::{DefaultErrorData, RpcError, RpcStatus};
+use juno::middleware::{Next, RpcCall};
+use juno::response::{Binary, ResponseMeta, RpcResponse};
+use juno::router::{RpcRouter, RpcType};
 use juno::rpc;
+use juno::subscription::{LastEventId, Tracked, tracked};
+use juno::upload::{ByteStream, FormData};
 use serde::Serialize;
+use serde_json::Value;
 use specta::Type;
+use std::time::{Duration, Instant};
 
-#[derive(Clone)]
+#[derive(Clone, FromRef)]
 struct AppState {
     shared_data: String,
+    version: VersionState,
+}
+
+#[derive(Clone)]
+struct VersionState {
+    api_version: &'static str,
+}
+
+#[derive(Clone)]
+struct RequestContext {
+    user_agent: Option<String>,
+}
+
+async fn create_context(_state: AppState, parts: Parts) -> Result<RequestContext, RpcError> {
+    let user_agent = parts
+        .headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    Ok(RequestContext { user_agent })
 }
 
 #[rpc(query)]
@@ -27,26 +61,222 @@ async fn get_user(State(state): State<AppState>, user_id: u64) -> Result<User, R
     }
 }
 
+#[rpc(query)]
+async fn get_guild(
+    #[input]
+    #[validate(range(min = 1))]
+    guild_id: u64,
+) -> Guild {
+    Guild {
+        id: guild_id,
+        name: "Test guild".to_string(),
+        users: Vec::new(),
+    }
+}
+
 #[rpc(query)]
 async fn get_server_time(State(state): State<AppState>) -> Result<String, RpcError> {
     println!("Shared state in get_server_time: {}", state.shared_data);
     Ok(chrono::Utc::now().to_rfc3339())
 }
 
+#[rpc(subscription)]
+async fn watch_server_time(interval_ms: u64) -> impl Stream<Item = String> {
+    let interval = tokio::time::interval(Duration::from_millis(interval_ms));
+    stream::unfold(interval, |mut interval| async move {
+        interval.tick().await;
+        Some((chrono::Utc::now().to_rfc3339(), interval))
+    })
+}
+
+#[rpc(subscription)]
+async fn watch_counter(#[extract] last_event_id: LastEventId) -> impl Stream<Item = Tracked<u64>> {
+    // Resume counting after the last event the client saw when it reconnects.
+    let start = last_event_id
+        .0
+        .and_then(|id| id.parse::<u64>().ok())
+        .map_or(0, |id| id + 1);
+
+    stream::unfold(start, |count| async move {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Some((tracked(count.to_string(), count), count + 1))
+    })
+}
+
 #[rpc(mutation)]
 async fn add_numbers(first: i32, second: i32) -> Result<i32, RpcError> {
     Ok(first + second)
 }
 
+#[rpc(mutation, max_body_size = 10 * 1024 * 1024)]
+async fn upload_files(#[extract] mut form: FormData) -> Result<Vec<UploadedFile>, RpcError> {
+    let mut files = Vec::new();
+    while let Some(mut file) = form.next_field().await? {
+        let name = file.file_name().unwrap_or_default().to_string();
+        let mut size = 0;
+        while let Some(chunk) = file.chunk().await? {
+            size += chunk.len() as u64;
+        }
+
+        files.push(UploadedFile { name, size });
+    }
+
+    Ok(files)
+}
+
+#[rpc(mutation)]
+async fn upload_avatar(#[extract] avatar: ByteStream) -> Result<u64, RpcError> {
+    let avatar = avatar.bytes().await?;
+    Ok(avatar.len() as u64)
+}
+
+#[rpc(query)]
+async fn export_users(count: u64) -> Binary {
+    let mut csv = "id,name\n".to_string();
+    for id in 0..count {
+        csv.push_str(&format!("{id},Test user\n"));
+    }
+
+    Binary::new(csv)
+        .content_type("text/csv")
+        .attachment("users.csv")
+}
+
 #[rpc(query)]
-async fn get_api_version() -> String {
-    "1.0.0".to_string()
+async fn get_api_version(
+    State(version): State<VersionState>,
+    #[extract] meta: ResponseMeta,
+) -> String {
+    meta.insert_header(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("max-age=60"),
+    );
+    version.api_version.to_string()
+}
+
+#[rpc(query)]
+async fn get_user_agent(#[extract] Ctx(ctx): Ctx<RequestContext>) -> Option<String> {
+    ctx.user_agent
+}
+
+#[rpc(query)]
+async fn get_header(#[extract] headers: HeaderMap, name: String) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+#[rpc(query, auth)]
+async fn get_me(#[extract] Identity(user): Identity<User>) -> User {
+    user
+}
+
+async fn verify_token(token: String, _state: AppState) -> Result<Option<User>, RpcError> {
+    let user = match token.as_str() {
+        "alice-token" => User {
+            id: 1,
+            name: "Alice".to_string(),
+            nick: None,
+        },
+        "bob-token" => User {
+            id: 2,
+            name: "Bob".to_string(),
+            nick: None,
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(user))
+}
+
+/// Only Alice is an admin
+struct Roles;
+
+impl PermissionResolver<AppState> for Roles {
+    type Identity = User;
+
+    async fn has_permission(
+        &self,
+        user: &User,
+        permission: &str,
+        _state: &AppState,
+    ) -> Result<bool, RpcError> {
+        Ok(permission == "admin" && user.id == 1)
+    }
+}
+
+#[rpc(mutation)]
+async fn invite_user(
+    #[validate(email)] email: String,
+    #[validate(range(min = 1, max = 10))] seats: Option<u32>,
+) -> Result<(), RpcError> {
+    println!("Inviting {} with {} seats", email, seats.unwrap_or(1));
+    Ok(())
+}
+
+#[rpc(mutation)]
+async fn create_session(#[extract] meta: ResponseMeta) -> String {
+    meta.set_status(StatusCode::CREATED);
+    meta.set_cookie(HeaderValue::from_static(
+        "session=alice-session; HttpOnly; SameSite=Lax; Path=/",
+    ));
+    "alice-session".to_string()
+}
+
+#[derive(Serialize, Type)]
+enum DeleteUserError {
+    LastAdmin,
+    HasOpenInvoices { count: u32 },
+}
+
+type DeleteUserResult<T> = Result<T, RpcError<DeleteUserError>>;
+
+#[rpc(mutation, requires = "admin")]
+async fn delete_user(user_id: u64) -> DeleteUserResult<()> {
+    match user_id {
+        1 => Err(RpcError::new(
+            RpcStatus::Conflict,
+            "Cannot delete the last admin".to_string(),
+        )
+        .with_details(DeleteUserError::LastAdmin)),
+        2 => Err(
+            RpcError::new(RpcStatus::Conflict, "User has open invoices".to_string())
+                .with_details(DeleteUserError::HasOpenInvoices { count: 3 }),
+        ),
+        _ => {
+            println!("Deleting user {}", user_id);
+            Ok(())
+        }
+    }
+}
+
+#[rpc(mutation, policy)]
+async fn update_nick(
+    user_id: u64,
+    #[validate(length(min = 1, max = 32))] nick: String,
+) -> Result<(), RpcError> {
+    println!("Setting the nick of user {} to {}", user_id, nick);
+    Ok(())
+}
+
+/// Users can only change their own nick
+impl Policy for UpdateNickInput {
+    type Identity = User;
+    type State = AppState;
+
+    async fn authorize(&self, user: &User, _state: &AppState, _parts: &Parts) -> Decision {
+        if self.user_id == user.id {
+            Decision::Allow
+        } else {
+            Decision::deny("You can only change your own nick")
+        }
+    }
 }
 
 #[rpc(query)]
 async fn no_output() -> () {
     println!("This function has no output. Just like me! waow");
-    ()
 }
 
 #[derive(Serialize, Type)]
@@ -57,25 +287,90 @@ pub struct Guild {
 }
 
 #[derive(Serialize, Type)]
+pub struct UploadedFile {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Serialize, Type)]
 pub struct User {
     pub id: u64,
     pub name: String,
     pub nick: Option<String>,
 }
 
+async fn log_calls(call: RpcCall<AppState>, next: Next<AppState>) -> Result<RpcResponse, RpcError> {
+    let path = call.path.clone();
+    let start = Instant::now();
+    let response = next.run(call).await;
+    println!(
+        "{} returned {} in {:?}",
+        path,
+        response.status(),
+        start.elapsed()
+    );
+    Ok(response)
+}
+
+#[derive(Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+struct ErrorData {
+    #[serde(flatten)]
+    default: DefaultErrorData,
+    path: String,
+}
+
+fn format_error(error: &RpcError, path: &str, _: RpcType, _: Option<&Value>) -> ErrorData {
+    ErrorData {
+        default: DefaultErrorData::new(error),
+        path: path.to_string(),
+    }
+}
+
 #[tokio::main]
 pub async fn main() {
     let app_state = AppState {
         shared_data: "Initial shared data".to_string(),
+        version: VersionState {
+            api_version: "1.0.0",
+        },
     };
 
+    let files = RpcRouter::new()
+        .for_state::<AppState>()
+        .add(upload_files)
+        .add(upload_avatar)
+        .add(export_users);
+
+    // Procedures that only need part of the app state can live in a router for that state
+    let meta = RpcRouter::new()
+        .for_state::<VersionState>()
+        .add(get_api_version);
+
     let rpc = RpcRouter::new()
         .for_state::<AppState>()
         .add(get_user)
         .add(get_server_time)
+        .add(get_guild)
+        .add(watch_server_time)
+        .add(watch_counter)
         .add(add_numbers)
-        .add(get_api_version)
         .add(no_output)
+        .add(get_user_agent)
+        .add(get_header)
+        .add(get_me)
+        .add(delete_user)
+        .add(create_session)
+        .add(invite_user)
+        .add(update_nick)
+        .nest("files", files)
+        .merge(meta)
+        .context(create_context)
+        .authenticator(BearerAuth::new(verify_token))
+        .permission_resolver(Roles)
+        .middleware(log_calls)
+        .error_formatter(format_error)
+        .zod_schemas(true)
         .write_client("client/src/@generated/server.ts")
         .unwrap()
         .to_router()
@@ -1,7 +1,8 @@
 use heck::ToPascalCase;
 use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
-use syn::{FnArg, ItemFn, Pat, ReturnType, Type};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse::Parser, FnArg, ItemFn, Pat, Type};
 
 #[proc_macro_attribute]
 pub fn rpc(
@@ -17,22 +18,78 @@ pub fn rpc(
 fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream> {
     let func: ItemFn = syn::parse2(input.clone())?;
 
-    let rpc_type_token = if args.is_empty() {
+    let mut is_subscription = false;
+    let mut rpc_type_token = None;
+    let mut max_body_size: Option<syn::Expr> = None;
+    let mut auth = false;
+    let mut policy = false;
+    let mut requires: Vec<syn::LitStr> = Vec::new();
+    let args_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("auth") {
+            auth = true;
+            return Ok(());
+        }
+
+        // The input struct's `Policy` impl is checked before the procedure runs
+        if meta.path.is_ident("policy") {
+            policy = true;
+            auth = true;
+            return Ok(());
+        }
+
+        // `requires = "admin"` or `requires = ["users:read", "users:write"]`, which imply `auth`
+        if meta.path.is_ident("requires") {
+            let value: syn::Expr = meta.value()?.parse()?;
+            let permissions = match value {
+                syn::Expr::Array(array) => array.elems.into_iter().collect(),
+                value => vec![value],
+            };
+            for permission in permissions {
+                match permission {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(permission),
+                        ..
+                    }) => requires.push(permission),
+                    permission => {
+                        return Err(syn::Error::new_spanned(
+                            permission,
+                            "Permissions must be string literals",
+                        ))
+                    }
+                }
+            }
+            auth = true;
+            return Ok(());
+        }
+
+        if meta.path.is_ident("max_body_size") {
+            max_body_size = Some(meta.value()?.parse()?);
+            return Ok(());
+        }
+
+        let rpc_type = if meta.path.is_ident("query") {
+            quote! { ::juno::router::RpcType::Query }
+        } else if meta.path.is_ident("mutation") {
+            quote! { ::juno::router::RpcType::Mutation }
+        } else if meta.path.is_ident("subscription") {
+            is_subscription = true;
+            quote! { ::juno::router::RpcType::Subscription }
+        } else {
+            return Err(meta.error("RPC type must be 'query', 'mutation' or 'subscription'"));
+        };
+
+        if rpc_type_token.replace(rpc_type).is_some() {
+            return Err(meta.error("RPC type can only be specified once"));
+        }
+        Ok(())
+    });
+    args_parser.parse2(args)?;
+
+    let Some(rpc_type_token) = rpc_type_token else {
         return Err(syn::Error::new(
             proc_macro2::Span::call_site(),
-            "RPC type must be specified as 'query' or 'mutation'",
+            "RPC type must be specified as 'query', 'mutation' or 'subscription'",
         ));
-    } else {
-        match args.to_string().trim().to_lowercase().as_str() {
-            "query" => quote! { ::juno::router::RpcType::Query },
-            "mutation" => quote! { ::juno::router::RpcType::Mutation },
-            _ => {
-                return Err(syn::Error::new(
-                    proc_macro2::Span::call_site(),
-                    "RPC type must be 'query' or 'mutation'",
-                ));
-            }
-        }
     };
 
     let og_func_name = &func.sig.ident;
@@ -44,8 +101,23 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
     let mut input_struct_fields = Vec::new();
     let mut inner_call_args = Vec::new();
     let mut state_arg_actual_type: Option<syn::Type> = None;
+    let mut extractor_args = Vec::new();
+    let mut extract_attr_types = Vec::new();
+    let mut validation_checks = Vec::new();
+    let mut validation_rules = Vec::new();
+    // An `#[input]` argument, which is the whole input rather than a field of the input struct
+    let mut value_input: Option<Type> = None;
 
-    let original_fn_inputs_for_inner_signature = func.sig.inputs.clone();
+    let mut original_fn_inputs_for_inner_signature = func.sig.inputs.clone();
+    for arg in &mut original_fn_inputs_for_inner_signature {
+        if let FnArg::Typed(pat_type) = arg {
+            pat_type.attrs.retain(|attr| {
+                !attr.path().is_ident("extract")
+                    && !attr.path().is_ident("validate")
+                    && !attr.path().is_ident("input")
+            });
+        }
+    }
 
     for arg in &func.sig.inputs {
         if let FnArg::Typed(pat_type) = arg {
@@ -72,10 +144,75 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
                 }
             }
 
+            let validate_attrs: Vec<_> = pat_type
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("validate"))
+                .collect();
+
+            // `#[input]` takes the whole input, e.g. `5` instead of `{ "user_id": 5 }`
+            if let Some(attr) = pat_type
+                .attrs
+                .iter()
+                .find(|attr| attr.path().is_ident("input"))
+            {
+                if value_input.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "Only one argument can be marked with #[input]",
+                    ));
+                }
+                // Docs only go on the fields of the input struct, which `#[input]` doesn't have
+                for attr in validate_attrs {
+                    parse_validate_attr(
+                        attr,
+                        None,
+                        arg_ty,
+                        &mut validation_checks,
+                        &mut validation_rules,
+                        &mut Vec::new(),
+                    )?;
+                }
+                value_input = Some((**arg_ty).clone());
+                inner_call_args.push(quote! { deserialized_input });
+                continue;
+            }
+
+            // `#[extract]` arguments are filled by any `FromRequestParts` extractor, including
+            // juno's own such as `Ctx<T>`, `LastEventId` or `FormData`
+            if pat_type
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("extract"))
+            {
+                if let Some(attr) = validate_attrs.first() {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "Only input arguments can be validated",
+                    ));
+                }
+                let extractor_ident = format_ident!("extractor_{}", extractor_args.len());
+                inner_call_args.push(quote! { #extractor_ident });
+                extractor_args.push((extractor_ident, arg_ty.clone()));
+                extract_attr_types.push(arg_ty.clone());
+                continue;
+            }
+
             // Regular argument, add to input struct and params for call
             if let Pat::Ident(pat_ident) = &**arg_pat {
                 let ident = &pat_ident.ident;
-                input_struct_fields.push(quote! { pub #ident: #arg_ty });
+                let mut docs = Vec::new();
+                for attr in validate_attrs {
+                    parse_validate_attr(
+                        attr,
+                        Some(ident),
+                        arg_ty,
+                        &mut validation_checks,
+                        &mut validation_rules,
+                        &mut docs,
+                    )?;
+                }
+                input_struct_fields.push(quote! { #(#[doc = #docs])* pub #ident: #arg_ty });
                 inner_call_args.push(quote! { deserialized_input.#ident });
             } else {
                 return Err(syn::Error::new_spanned(arg_pat, "Unsupported argument pattern in RPC function. Only simple identifiers are supported for non-State arguments."));
@@ -85,89 +222,21 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
         }
     }
 
-    // Determine the actual T type for Specta and if the original function returns Result<T, RpcError>
-    let (output_type_for_specta, original_fn_returns_result): (TokenStream, bool) = {
-        match &func.sig.output {
-            ReturnType::Default => {
-                // Corresponds to `-> ()`. Treat as `T = ()`.
-                (quote! { () }, false)
-            }
-            ReturnType::Type(_, ty_ref) => {
-                // Corresponds to `-> SomeType`.
-                // We need to check if `SomeType` is `Result<ActualT, RpcError>` or just `ActualT`.
-                let ty = &**ty_ref; // Dereference Box<Type> to Type
-
-                if let Type::Path(type_path) = ty {
-                    // It's a path type, like `String`, `User`, or `Result<User, RpcError>`.
-                    if let Some(last_segment) = type_path.path.segments.last() {
-                        if last_segment.ident == "Result" {
-                            // It's a `Result<...>`
-                            if let syn::PathArguments::AngleBracketed(angle_args) =
-                                &last_segment.arguments
-                            {
-                                if angle_args.args.len() == 2 {
-                                    let t_arg = &angle_args.args[0];
-                                    let e_arg = &angle_args.args[1];
-
-                                    let actual_t_tokens =
-                                        if let syn::GenericArgument::Type(inner_t) = t_arg {
-                                            quote! { #inner_t }
-                                        } else {
-                                            return Err(syn::Error::new_spanned(
-                                            t_arg,
-                                            "The first type argument of Result (T) must be a type.",
-                                        ));
-                                        };
-
-                                    // Check if the error part is RpcError
-                                    if let syn::GenericArgument::Type(err_ty) = e_arg {
-                                        if let Type::Path(err_type_path) = &*err_ty {
-                                            if err_type_path
-                                                .path
-                                                .segments
-                                                .last()
-                                                .map_or(false, |s| s.ident == "RpcError")
-                                            {
-                                                // Confirmed: Result<ActualT, RpcError>
-                                                (actual_t_tokens, true)
-                                            } else {
-                                                return Err(syn::Error::new_spanned(
-                                                    err_ty,
-                                                    "If returning a Result, the error type must be RpcError (e.g., Result<MyType, RpcError>).",
-                                                ));
-                                            }
-                                        } else {
-                                            return Err(syn::Error::new_spanned(err_ty, "The second type argument of Result (E) must be a path type (expected RpcError)."));
-                                        }
-                                    } else {
-                                        return Err(syn::Error::new_spanned(e_arg, "The second type argument of Result (E) must be a type."));
-                                    }
-                                } else {
-                                    return Err(syn::Error::new_spanned(
-                                        &last_segment.arguments,
-                                        "Result must have exactly two type arguments: Result<T, RpcError>.",
-                                    ));
-                                }
-                            } else {
-                                return Err(syn::Error::new_spanned(
-                                    ty,
-                                    "Result type must use angle bracketed arguments like Result<MyType, RpcError>.",
-                                ));
-                            }
-                        } else {
-                            // Not `Result<...>`, so it's a direct type `T`.
-                            (quote! { #ty }, false)
-                        }
-                    } else {
-                        // Type path has no segments. This implies `ty` itself is the type `T`.
-                        (quote! { #ty }, false)
-                    }
-                } else {
-                    // Not a Type::Path (e.g., `(i32, String)`, `[i32; 3]`). This is the type `T`.
-                    (quote! { #ty }, false)
-                }
-            }
-        }
+    if let (Some(value_ty), Some(field)) = (&value_input, input_struct_fields.first()) {
+        return Err(syn::Error::new_spanned(
+            field,
+            format!(
+                "{} is the whole input, so it cannot be combined with other input arguments",
+                quote!(#value_ty)
+            ),
+        ));
+    }
+
+    // The type the input is deserialized into, if the procedure takes one
+    let input_ty = match &value_input {
+        Some(value_ty) => Some(quote! { #value_ty }),
+        None if !input_struct_fields.is_empty() => Some(quote! { #input_struct_name }),
+        None => None,
     };
 
     let state_extraction_logic = if let Some(actual_state_type) = &state_arg_actual_type {
@@ -191,8 +260,25 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
         }
     };
 
-    let deserialization_logic = if input_struct_fields.is_empty() {
+    let extractor_logic = extractor_args.iter().map(|(extractor_ident, extractor_ty)| {
         quote! {
+            // Rejections are turned into errors straight away, as they aren't necessarily `Send`
+            let #extractor_ident = <#extractor_ty as axum::extract::FromRequestParts<_>>::from_request_parts(&mut parts, &state_param)
+                .await
+                .map_err(::juno::errors::RpcError::from_rejection);
+            let #extractor_ident = match #extractor_ident {
+                Ok(value) => value,
+                Err(err) => {
+                    let mut err = err.await;
+                    err.message = format!("Failed to extract {}: {}", stringify!(#extractor_ty), err.message);
+                    return err.into_rpc_response();
+                }
+            };
+        }
+    });
+
+    let deserialization_logic = match (&input_ty, &value_input) {
+        (None, _) => quote! {
             // Validate that the input is either an empty object, null, or missing entirely
             if let Some(value) = &input_json {
                 if !value.is_null() && !value.as_object().map_or(false, |obj| obj.is_empty()) {
@@ -202,27 +288,84 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
                     ).into_rpc_response();
                 }
             }
-        }
-    } else {
-        quote! {
-            let deserialized_input: #input_struct_name = match input_json {
-                None => {
+        },
+        (Some(input_ty), Some(_)) => quote! {
+            let deserialized_input: #input_ty = match ::juno::validation::deserialize_value(
+                input_json,
+                stringify!(#input_ty),
+            ) {
+                Ok(input) => input,
+                Err(err) => return err.into_rpc_response(),
+            };
+        },
+        (Some(input_ty), None) => quote! {
+            let deserialized_input: #input_ty = match ::juno::validation::deserialize_input(
+                input_json,
+                stringify!(#input_ty),
+            ) {
+                Ok(input) => input,
+                Err(err) => return err.into_rpc_response(),
+            };
+        },
+    };
+
+    // Every failed check is reported at once, so forms can show all of them
+    let validation_logic =
+        if let (false, Some(input_ty)) = (validation_checks.is_empty(), &input_ty) {
+            quote! {
+                let issues: Vec<::juno::validation::ValidationIssue> = [#(#validation_checks),*]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                if !issues.is_empty() {
                     return ::juno::errors::RpcError::new(
                         ::juno::errors::RpcStatus::BadRequest,
-                        "Missing input arguments".to_string(),
-                    ).into_rpc_response();
+                        format!("Invalid input for '{}'", stringify!(#input_ty)),
+                    )
+                    .with_issues(issues)
+                    .into_rpc_response();
                 }
-                Some(value) => match serde_json::from_value::<#input_struct_name>(value) {
-                    Ok(input) => input,
-                    Err(err) => {
-                        return ::juno::errors::RpcError::new(
-                            ::juno::errors::RpcStatus::BadRequest,
-                            format!("Failed to deserialize input for '{}': {}", stringify!(#input_struct_name), err),
-                        ).into_rpc_response();
-                    }
-                },
+            }
+        } else {
+            quote! {}
+        };
+
+    let policy_logic = if policy {
+        let Some(input_ty) = &input_ty else {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "A policy is implemented for the procedure's input, so the procedure must take an input",
+            ));
+        };
+
+        quote! {
+            let Some(::juno::auth::Identity(policy_identity)) = parts
+                .extensions
+                .get::<::juno::auth::Identity<<#input_ty as ::juno::auth::Policy>::Identity>>()
+            else {
+                return ::juno::errors::RpcError::new(
+                    ::juno::errors::RpcStatus::Unauthorized,
+                    "Authentication required".to_string(),
+                ).into_rpc_response();
             };
+            let policy_state = <<#input_ty as ::juno::auth::Policy>::State as axum::extract::FromRef<S>>::from_ref(&state_param);
+            let decision = ::juno::auth::Policy::authorize(&deserialized_input, policy_identity, &policy_state, &parts).await;
+            if let ::juno::auth::Decision::Deny(reason) = decision {
+                return ::juno::errors::RpcError::new(
+                    ::juno::errors::RpcStatus::Forbidden,
+                    reason,
+                ).into_rpc_response();
+            }
         }
+    } else {
+        quote! {}
+    };
+
+    // The input struct is private, which only matters to the bounds a policy adds
+    let private_bounds_allow = if policy {
+        quote! { #[allow(private_bounds)] }
+    } else {
+        quote! {}
     };
 
     let original_func_body = &func.block;
@@ -230,20 +373,27 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
     let original_func_output_type = &func.sig.output;
     let original_func_attrs = &func.attrs;
 
-    let (wrapper_fn_generics, state_param_type_for_handler, rpc_method_state_type) =
-        if let Some(actual_state_type) = &state_arg_actual_type {
-            (
-                quote! {},                     // No extra generics for the wrapper fn itself
-                quote! { #actual_state_type }, // Handler takes the concrete state type
-                quote! { #actual_state_type }, // RpcMethod is for the concrete state type
-            )
-        } else {
-            (
-                quote! { <S: Clone + Send + Sync + 'static> }, // Wrapper fn is generic
-                quote! { S },                                  // Handler takes generic S
-                quote! { S },                                  // RpcMethod is for generic S
-            )
-        };
+    // The wrapper is generic over the router's state. A State<T> argument only needs T to be
+    // derivable from it, the same way axum substates work.
+    let mut where_predicates = Vec::new();
+    if let Some(actual_state_type) = &state_arg_actual_type {
+        where_predicates.push(quote! { #actual_state_type: axum::extract::FromRef<S> });
+    }
+    if let (true, Some(input_ty)) = (policy, &input_ty) {
+        where_predicates.push(quote! { #input_ty: ::juno::auth::Policy });
+        where_predicates.push(quote! {
+            <#input_ty as ::juno::auth::Policy>::State: axum::extract::FromRef<S>
+        });
+    }
+    // Extractors may only be implemented for a specific state
+    for extract_ty in &extract_attr_types {
+        where_predicates.push(quote! { #extract_ty: axum::extract::FromRequestParts<S> });
+    }
+    let state_where_clause = if where_predicates.is_empty() {
+        quote! {}
+    } else {
+        quote! { where #(#where_predicates),* }
+    };
 
     let input_struct_definition = if input_struct_fields.is_empty() {
         quote! {}
@@ -256,31 +406,80 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
         }
     };
 
-    let input_type_ref_logic = if input_struct_fields.is_empty() {
-        quote! { None }
+    let input_type_ref_logic = if let Some(input_ty) = &input_ty {
+        quote! { Some(<#input_ty as specta::Type>::reference(types, &[])) }
     } else {
-        quote! { Some(<#input_struct_name as specta::Type>::reference(types, &[])) }
+        quote! { None }
     };
 
-    let output_type_ref_logic = if output_type_for_specta.to_string() == "()" {
-        quote! { None }
+    // Whether the procedure returns a result is decided by its type rather than how it's spelled,
+    // see `juno::returns`. The inner function is never called here, it only names that type.
+    let unreachable_args = inner_call_args.iter().map(|_| quote! { unreachable!() });
+    let returned_probe = quote! {
+        #[allow(unreachable_code)]
+        let returned = ::juno::returns::Returned::of(|| #inner_func_name(#(#unreachable_args),*));
+        let returned = (&&returned).kind().probe(returned);
+    };
+
+    // Subscriptions return `impl Stream<Item = T>`, so the client sees `T` rather than the stream.
+    let (output_type_ref_logic, tracked_logic, output_format) = if is_subscription {
+        (
+            quote! {{
+                let items = returned.items();
+                let items = (&&items).kind().probe(items);
+                Some(items.event_type(types))
+            }},
+            quote! {{
+                let items = returned.items();
+                (&&items).kind().probe(items).tracked()
+            }},
+            quote! { ::juno::router::OutputFormat::Json },
+        )
     } else {
-        quote! { Some(<#output_type_for_specta as specta::Type>::reference(types, &[])) }
+        (
+            quote! { returned.output_type(types) },
+            quote! { false },
+            quote! { returned.output_format() },
+        )
     };
 
-    let handler_result_processing = if original_fn_returns_result {
+    let handler_result_processing = if is_subscription {
         quote! {
-            let result = #inner_func_name(#(#inner_call_args),*).await;
-            result.into_rpc_response()
+            let returned = #inner_func_name(#(#inner_call_args),*).await;
+            let stream = match (&&::juno::returns::Returned::of_value(&returned)).kind().wrap(returned) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    return ::juno::errors::IntoRpcError::into_rpc_error(err).into_rpc_response();
+                }
+            };
+            (&&::juno::returns::Returned::items_of(&stream)).kind().subscription(stream)
         }
     } else {
         quote! {
-            let result_value = #inner_func_name(#(#inner_call_args),*).await;
-            use ::juno::response::IntoRpcResponse;
-            Ok(result_value).into_rpc_response()
+            let returned = #inner_func_name(#(#inner_call_args),*).await;
+            (&&::juno::returns::Returned::of_value(&returned))
+                .kind()
+                .wrap(returned)
+                .map_err(::juno::errors::IntoRpcError::into_rpc_error)
+                .into_rpc_response()
         }
     };
 
+    // A FormData or ByteStream argument reads the request body in place of a JSON input. Like
+    // returns, that is decided by the argument's type, see `juno::extracts`.
+    let extracted_bodies = extract_attr_types.iter().map(|extract_ty| {
+        quote! {{
+            let extracted = ::juno::extracts::Extracted::<#extract_ty>::new();
+            (&&extracted).kind().body(extracted, types)
+        }}
+    });
+    let has_input = input_ty.is_some();
+
+    let max_body_size_logic = match &max_body_size {
+        Some(max_body_size) => quote! { Some(#max_body_size) },
+        None => quote! { None },
+    };
+
     let gen = quote! {
         #(#original_func_attrs)*
         #original_func_asyncness fn #inner_func_name(#original_fn_inputs_for_inner_signature) #original_func_output_type {
@@ -289,23 +488,40 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
 
         #input_struct_definition
 
-        pub fn #export_func_name #wrapper_fn_generics (
+        #private_bounds_allow
+        pub fn #export_func_name<S: Clone + Send + Sync + 'static>(
             types: &mut specta::TypeCollection,
-        ) -> ::juno::router::RpcMethod<#rpc_method_state_type> {
+        ) -> ::juno::router::RpcMethod<S> #state_where_clause {
+            use ::juno::extracts::{BodyKind as _, PartsKind as _};
+            use ::juno::returns::{ResultKind as _, ResultLikeKind as _, ValueKind as _};
             let name = #func_name_str;
             let rpc_type = #rpc_type_token;
 
-            let input_type_ref = #input_type_ref_logic;
+            #returned_probe
+            let body_input = ::juno::extracts::body_input(
+                name,
+                rpc_type,
+                #has_input,
+                [#(#extracted_bodies),*],
+            );
+            let (input_format, input_type_ref) = match body_input {
+                Some(body) => (body.format, Some(body.input_type)),
+                None => (::juno::router::InputFormat::Json, #input_type_ref_logic),
+            };
             let output_type_ref = #output_type_ref_logic;
 
             let handler = std::sync::Arc::new(
-                move |input_json: Option<serde_json::Value>, state_param: #state_param_type_for_handler, mut parts: axum::http::request::Parts| {
+                move |input_json: Option<serde_json::Value>, state_param: S, mut parts: axum::http::request::Parts| {
                     Box::pin(async move {
                         use axum::extract::FromRequestParts as _;
                         use ::juno::response::IntoRpcResponse as _;
+                        use ::juno::returns::{ResultKind as _, ResultLikeKind as _, ValueKind as _};
 
                         #state_extraction_logic
+                        #(#extractor_logic)*
                         #deserialization_logic
+                        #validation_logic
+                        #policy_logic
 
                         #handler_result_processing
                     }) as std::pin::Pin<Box<dyn std::future::Future<Output = ::juno::response::RpcResponse> + Send>>
@@ -317,6 +533,18 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
                 rpc_type,
                 input_type: input_type_ref,
                 output_type: output_type_ref,
+                error_type: returned.error_type(types),
+                tracked: #tracked_logic,
+                input_format,
+                output_format: #output_format,
+                max_body_size: #max_body_size_logic,
+                auth: #auth,
+                requires: &[#(#requires),*],
+                rules: {
+                    const RULES: &[(&str, ::juno::validation::Rule)] = &[#(#validation_rules),*];
+                    RULES
+                },
+                middleware: Vec::new(),
                 handler,
             }
         }
@@ -324,3 +552,111 @@ fn transform(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream>
 
     Ok(gen)
 }
+
+/// Parses `#[validate(length(min = 1, max = 64), range(min = 1), email)]` on an input argument
+/// into checks of the deserialized input, the rules kept for the client and doc lines describing
+/// them. Bounds must be constants.
+///
+/// `ident` is the argument's field in the input struct, or `None` for an `#[input]` argument,
+/// which is checked as the whole input.
+fn parse_validate_attr(
+    attr: &syn::Attribute,
+    ident: Option<&syn::Ident>,
+    ty: &Type,
+    checks: &mut Vec<TokenStream>,
+    rules: &mut Vec<TokenStream>,
+    docs: &mut Vec<String>,
+) -> syn::Result<()> {
+    let field = ident.map(ToString::to_string).unwrap_or_default();
+    let value = match ident {
+        Some(ident) => quote! { &deserialized_input.#ident },
+        None => quote! { &deserialized_input },
+    };
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("email") {
+            checks.push(quote! {
+                ::juno::validation::email(#field, #value)
+            });
+            rules.push(quote! { (#field, ::juno::validation::Rule::Email) });
+            docs.push("Must be an email address".to_string());
+            return Ok(());
+        }
+
+        let is_length = meta.path.is_ident("length");
+        if !is_length && !meta.path.is_ident("range") {
+            return Err(meta.error("Expected `length(..)`, `range(..)` or `email`"));
+        }
+
+        let mut min: Option<syn::Expr> = None;
+        let mut max: Option<syn::Expr> = None;
+        meta.parse_nested_meta(|bound| {
+            if bound.path.is_ident("min") {
+                min = Some(bound.value()?.parse()?);
+            } else if bound.path.is_ident("max") {
+                max = Some(bound.value()?.parse()?);
+            } else {
+                return Err(bound.error("Expected `min` or `max`"));
+            }
+            Ok(())
+        })?;
+
+        let doc_subject = if is_length {
+            "Length must be"
+        } else {
+            "Must be"
+        };
+        docs.push(match (&min, &max) {
+            (Some(min), Some(max)) => format!(
+                "{doc_subject} between {} and {}",
+                quote!(#min),
+                quote!(#max)
+            ),
+            (Some(min), None) => format!("{doc_subject} at least {}", quote!(#min)),
+            (None, Some(max)) => format!("{doc_subject} at most {}", quote!(#max)),
+            (None, None) => return Err(meta.error("Expected a `min` or `max` bound")),
+        });
+
+        if is_length {
+            let bound = |bound: &Option<syn::Expr>| match bound {
+                Some(bound) => quote! { Some(#bound) },
+                None => quote! { None },
+            };
+            let (min, max) = (bound(&min), bound(&max));
+            rules.push(quote! {
+                (#field, ::juno::validation::Rule::Length { min: #min, max: #max })
+            });
+            checks.push(quote! {
+                ::juno::validation::length(#field, #value, #min, #max)
+            });
+            return Ok(());
+        }
+
+        // Range bounds are constants of the field's own number type, so a bound that doesn't
+        // fit it, e.g. `300` for a `u8` or `1.5` for a `u64`, fails to compile rather than
+        // being checked as some other value. Large integers are also compared exactly.
+        let bound = |bound: &Option<syn::Expr>| match bound {
+            Some(bound) => {
+                let constant = quote_spanned! {bound.span()=>
+                    {
+                        #[deny(overflowing_literals)]
+                        const BOUND: <#ty as ::juno::validation::ValidateRange>::Number = #bound;
+                        BOUND
+                    }
+                };
+                (
+                    quote! { Some(#constant) },
+                    quote! { Some(#constant as f64) },
+                )
+            }
+            None => (quote! { None }, quote! { None }),
+        };
+        let ((check_min, rule_min), (check_max, rule_max)) = (bound(&min), bound(&max));
+        rules.push(quote! {
+            (#field, ::juno::validation::Rule::Range { min: #rule_min, max: #rule_max })
+        });
+        checks.push(quote! {
+            ::juno::validation::range(#field, #value, #check_min, #check_max)
+        });
+        Ok(())
+    })
+}
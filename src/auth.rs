@@ -0,0 +1,305 @@
+use crate::errors::{RpcError, RpcStatus};
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, StatusCode, header, request::Parts},
+};
+use std::ops::Deref;
+
+/// Resolves who is making a request, for procedures marked with `#[rpc(.., auth)]`.
+///
+/// Set one for a router with [`RpcRouter::authenticator`](crate::router::RpcRouter::authenticator).
+/// Returning `None` rejects the call with `UNAUTHORIZED` before the procedure's input is read.
+pub trait Authenticator<S>: Send + Sync + 'static {
+    type Identity: Clone + Send + Sync + 'static;
+
+    fn authenticate(
+        &self,
+        parts: &Parts,
+        state: &S,
+    ) -> impl Future<Output = Result<Option<Self::Identity>, RpcError>> + Send;
+}
+
+/// The identity an [`Authenticator`] resolved for the request. Add it as an `#[extract]`
+/// argument to a procedure marked with `#[rpc(.., auth)]` to receive it.
+#[derive(Clone, Debug)]
+pub struct Identity<T>(pub T);
+
+impl<T> Deref for Identity<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<S, T> FromRequestParts<S> for Identity<T>
+where
+    S: Send + Sync,
+    T: Clone + Send + Sync + 'static,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Identity<T>>().cloned().ok_or((
+            StatusCode::UNAUTHORIZED,
+            "The procedure must be marked with `auth` to receive an identity",
+        ))
+    }
+}
+
+/// Checks the permissions procedures declare with `#[rpc(.., requires = "..")]`.
+///
+/// Set one for a router with
+/// [`RpcRouter::permission_resolver`](crate::router::RpcRouter::permission_resolver). The
+/// identity type must match the one returned by the router's [`Authenticator`].
+pub trait PermissionResolver<S>: Send + Sync + 'static {
+    type Identity: Clone + Send + Sync + 'static;
+
+    fn has_permission(
+        &self,
+        identity: &Self::Identity,
+        permission: &str,
+        state: &S,
+    ) -> impl Future<Output = Result<bool, RpcError>> + Send;
+}
+
+/// Authenticates requests with an `Authorization: Bearer <token>` header, resolving the token
+/// to an identity with `verify`.
+///
+/// ```ignore
+/// RpcRouter::new().authenticator(BearerAuth::new(|token: String, state: AppState| async move {
+///     Ok(state.sessions.user_for_token(&token))
+/// }))
+/// ```
+pub struct BearerAuth<F> {
+    verify: F,
+}
+
+impl<F> BearerAuth<F> {
+    pub fn new(verify: F) -> Self {
+        Self { verify }
+    }
+}
+
+impl<S, F, Fut, I> Authenticator<S> for BearerAuth<F>
+where
+    S: Clone + Send + Sync + 'static,
+    F: Fn(String, S) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<I>, RpcError>> + Send,
+    I: Clone + Send + Sync + 'static,
+{
+    type Identity = I;
+
+    async fn authenticate(&self, parts: &Parts, state: &S) -> Result<Option<I>, RpcError> {
+        match bearer_token(&parts.headers) {
+            Some(token) => (self.verify)(token.to_string(), state.clone()).await,
+            None => Ok(None),
+        }
+    }
+}
+
+/// Authenticates requests with a session cookie, resolving the cookie's value to an identity
+/// with `verify`.
+pub struct SessionCookieAuth<F> {
+    cookie_name: String,
+    verify: F,
+}
+
+impl<F> SessionCookieAuth<F> {
+    pub fn new(cookie_name: impl Into<String>, verify: F) -> Self {
+        Self {
+            cookie_name: cookie_name.into(),
+            verify,
+        }
+    }
+}
+
+impl<S, F, Fut, I> Authenticator<S> for SessionCookieAuth<F>
+where
+    S: Clone + Send + Sync + 'static,
+    F: Fn(String, S) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<I>, RpcError>> + Send,
+    I: Clone + Send + Sync + 'static,
+{
+    type Identity = I;
+
+    async fn authenticate(&self, parts: &Parts, state: &S) -> Result<Option<I>, RpcError> {
+        match cookie(&parts.headers, &self.cookie_name) {
+            Some(session) => (self.verify)(session.to_string(), state.clone()).await,
+            None => Ok(None),
+        }
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.trim_matches('"'))
+        .filter(|value| !value.is_empty())
+}
+
+pub(crate) fn unauthorized() -> RpcError {
+    RpcError::new(
+        RpcStatus::Unauthorized,
+        "Authentication required".to_string(),
+    )
+}
+
+pub(crate) fn forbidden(path: &str, permission: &str) -> RpcError {
+    RpcError::new(
+        RpcStatus::Forbidden,
+        format!("`{}` requires the `{}` permission", path, permission),
+    )
+}
+
+/// The outcome of a [`Policy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// Rejects the call with `FORBIDDEN`, using the reason as the error message.
+    Deny(String),
+}
+
+impl Decision {
+    pub fn deny(reason: impl Into<String>) -> Self {
+        Self::Deny(reason.into())
+    }
+}
+
+/// Decides whether an identity may call a procedure with a specific input, e.g. whether a user
+/// may delete the user with the given id.
+///
+/// Implement it for the `{Name}Input` struct `#[rpc]` generates for a procedure, then mark the
+/// procedure with `#[rpc(.., policy)]`. The policy runs after the input is deserialized and
+/// before the procedure, and since it is a plain method it can be tested on its own.
+pub trait Policy {
+    type Identity: Clone + Send + Sync + 'static;
+    /// The state the policy needs, derived from the router's state with
+    /// [`FromRef`](axum::extract::FromRef). Contexts can be read from `parts` with
+    /// [`Ctx::from_parts`](crate::context::Ctx::from_parts).
+    type State: Send + Sync;
+
+    fn authorize(
+        &self,
+        identity: &Self::Identity,
+        state: &Self::State,
+        parts: &Parts,
+    ) -> impl Future<Output = Decision> + Send;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use futures_util::FutureExt;
+
+    fn parts(headers: &[(header::HeaderName, &str)]) -> Parts {
+        let mut request = Request::builder();
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    fn authenticate<A: Authenticator<()>>(
+        authenticator: &A,
+        parts: &Parts,
+    ) -> Result<Option<A::Identity>, RpcError> {
+        authenticator
+            .authenticate(parts, &())
+            .now_or_never()
+            .expect("the verifier should not wait")
+    }
+
+    /// Accepts the token `valid`, and fails for the token `revoked`.
+    fn verify(token: String, _state: ()) -> std::future::Ready<Result<Option<String>, RpcError>> {
+        std::future::ready(match token.as_str() {
+            "valid" => Ok(Some("alice".to_string())),
+            "revoked" => Err(RpcError::new(
+                RpcStatus::Unauthorized,
+                "Token revoked".to_string(),
+            )),
+            _ => Ok(None),
+        })
+    }
+
+    #[test]
+    fn bearer_token_requires_the_bearer_scheme() {
+        let token = |value: &str| {
+            bearer_token(&parts(&[(header::AUTHORIZATION, value)]).headers).map(str::to_string)
+        };
+        assert_eq!(token("Bearer abc"), Some("abc".to_string()));
+        assert_eq!(token("bearer abc"), Some("abc".to_string()));
+        assert_eq!(token("BEARER  abc "), Some("abc".to_string()));
+        assert_eq!(token("Basic abc"), None);
+        assert_eq!(token("Bearer"), None);
+        assert_eq!(token("Bearer "), None);
+        assert_eq!(token("abc"), None);
+        assert_eq!(bearer_token(&parts(&[]).headers), None);
+    }
+
+    #[test]
+    fn bearer_auth_resolves_identities() {
+        let auth = BearerAuth::new(verify);
+        let with_token = |value: &str| parts(&[(header::AUTHORIZATION, value)]);
+
+        assert_eq!(
+            authenticate(&auth, &with_token("Bearer valid")).unwrap(),
+            Some("alice".to_string())
+        );
+        assert_eq!(
+            authenticate(&auth, &with_token("Bearer unknown")).unwrap(),
+            None
+        );
+        // Without a token the verifier isn't asked
+        assert_eq!(authenticate(&auth, &parts(&[])).unwrap(), None);
+        assert_eq!(
+            authenticate(&auth, &with_token("Basic valid")).unwrap(),
+            None
+        );
+
+        let err = authenticate(&auth, &with_token("Bearer revoked")).unwrap_err();
+        assert_eq!(err.status, RpcStatus::Unauthorized);
+        assert_eq!(err.message, "Token revoked");
+    }
+
+    #[test]
+    fn cookie_finds_the_named_cookie() {
+        let headers = parts(&[
+            (header::COOKIE, "theme=dark; xsession=other"),
+            (header::COOKIE, "session=\"abc=123\";lang=en"),
+        ])
+        .headers;
+        assert_eq!(cookie(&headers, "session"), Some("abc=123"));
+        assert_eq!(cookie(&headers, "theme"), Some("dark"));
+        assert_eq!(cookie(&headers, "lang"), Some("en"));
+        assert_eq!(cookie(&headers, "missing"), None);
+
+        let headers = parts(&[(header::COOKIE, "session=; other=1")]).headers;
+        assert_eq!(cookie(&headers, "session"), None);
+    }
+
+    #[test]
+    fn session_cookie_auth_resolves_identities() {
+        let auth = SessionCookieAuth::new("session", |session: String, _state: ()| {
+            std::future::ready(Ok(Some(session)))
+        });
+        assert_eq!(
+            authenticate(&auth, &parts(&[(header::COOKIE, "a=1; session=\"s1\"")])).unwrap(),
+            Some("s1".to_string())
+        );
+        assert_eq!(authenticate(&auth, &parts(&[])).unwrap(), None);
+    }
+}
@@ -0,0 +1,50 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{StatusCode, request::Parts},
+};
+use std::ops::{Deref, DerefMut};
+
+/// Per-request context, like tRPC's `ctx`.
+///
+/// The context is built for each request by the function given to
+/// [`RpcRouter::context`](crate::router::RpcRouter::context), and middleware can change or add
+/// to it with [`RpcCall::set_context`](crate::middleware::RpcCall::set_context). Add a
+/// `#[extract] ctx: Ctx<MyCtx>` argument to a procedure to receive it.
+#[derive(Clone, Debug, Default)]
+pub struct Ctx<T>(pub T);
+
+impl<T: Send + Sync + 'static> Ctx<T> {
+    /// The context of type `T` stored in `parts`, if one has been set.
+    pub fn from_parts(parts: &Parts) -> Option<&T> {
+        parts.extensions.get::<Ctx<T>>().map(|ctx| &ctx.0)
+    }
+}
+
+impl<T> Deref for Ctx<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Ctx<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<S, T> FromRequestParts<S> for Ctx<T>
+where
+    S: Send + Sync,
+    T: Clone + Send + Sync + 'static,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Ctx<T>>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "No context of this type was created for the request",
+        ))
+    }
+}
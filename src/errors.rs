@@ -1,6 +1,17 @@
-use crate::response::{IntoRpcResponse, RpcResponse};
-use axum::http::StatusCode;
-use serde_json::json;
+use crate::{
+    response::{IntoRpcResponse, RpcResponse},
+    validation::ValidationIssue,
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+use specta::{TypeCollection, datatype::reference::Reference};
+use std::any::TypeId;
+
+const MAX_REJECTION_SIZE: usize = 64 * 1024; // 64 KB
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RpcStatus {
@@ -50,6 +61,31 @@ impl RpcStatus {
         }
     }
 
+    /// The status matching an HTTP error status. Other 4xx statuses become `BadRequest` and
+    /// anything else `InternalServerError`.
+    pub fn from_http_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => RpcStatus::Unauthorized,
+            StatusCode::FORBIDDEN => RpcStatus::Forbidden,
+            StatusCode::NOT_FOUND => RpcStatus::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => RpcStatus::MethodNotSupported,
+            StatusCode::REQUEST_TIMEOUT => RpcStatus::Timeout,
+            StatusCode::CONFLICT => RpcStatus::Conflict,
+            StatusCode::PRECONDITION_FAILED => RpcStatus::PreconditionFailed,
+            StatusCode::PAYLOAD_TOO_LARGE => RpcStatus::PayloadTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => RpcStatus::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => RpcStatus::UnprocessableContent,
+            StatusCode::TOO_MANY_REQUESTS => RpcStatus::TooManyRequests,
+            StatusCode::NOT_IMPLEMENTED => RpcStatus::NotImplemented,
+            StatusCode::BAD_GATEWAY => RpcStatus::BadGateway,
+            StatusCode::SERVICE_UNAVAILABLE => RpcStatus::ServiceUnavailable,
+            StatusCode::GATEWAY_TIMEOUT => RpcStatus::GatewayTimeout,
+            status if status.is_client_error() => RpcStatus::BadRequest,
+            _ => RpcStatus::InternalServerError,
+        }
+    }
+
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match self {
             RpcStatus::ParseError => "PARSE_ERROR".to_string(),
@@ -99,21 +135,80 @@ impl RpcStatus {
     }
 }
 
+/// An error returned to the client.
+///
+/// `D` is the type of the error's `details`, sent to the client in `error.data.details`.
+/// Procedures returning `Result<T, RpcError<D>>` have `D` exported to TypeScript, so the client
+/// can tell domain errors apart without parsing messages.
 #[derive(Debug, Clone)]
-pub struct RpcError {
+pub struct RpcError<D = Value> {
     pub status: RpcStatus,
     pub message: String,
+    pub details: Option<D>,
+    /// Problems with the input, sent to the client in `error.data.issues`.
+    pub issues: Vec<ValidationIssue>,
 }
 
 impl RpcError {
     pub fn new(status: RpcStatus, message: String) -> Self {
-        Self { status, message }
+        Self {
+            status,
+            message,
+            details: None,
+            issues: Vec::new(),
+        }
+    }
+
+    /// Adds typed details to the error, e.g. a variant of a `DeleteUserError` enum.
+    pub fn with_details<D>(self, details: D) -> RpcError<D> {
+        RpcError {
+            status: self.status,
+            message: self.message,
+            details: Some(details),
+            issues: self.issues,
+        }
+    }
+
+    /// Turns the error into one for a procedure with typed details, dropping any details it
+    /// already has.
+    pub fn typed<D>(self) -> RpcError<D> {
+        RpcError {
+            status: self.status,
+            message: self.message,
+            details: None,
+            issues: self.issues,
+        }
+    }
+
+    /// Turns an extractor's rejection into an error with the same status, using the rejection's
+    /// body as the message.
+    pub fn from_rejection(rejection: impl IntoResponse) -> impl Future<Output = Self> + Send {
+        let response = rejection.into_response();
+        async move {
+            let status = response.status();
+            let message = match axum::body::to_bytes(response.into_body(), MAX_REJECTION_SIZE).await
+            {
+                Ok(body) if !body.is_empty() => String::from_utf8_lossy(&body).into_owned(),
+                _ => status.canonical_reason().unwrap_or_default().to_string(),
+            };
+
+            Self::new(RpcStatus::from_http_status(status), message)
+        }
+    }
+}
+
+impl<D> RpcError<D> {
+    /// Adds problems with specific fields of the input, e.g. from validating it in the procedure.
+    pub fn with_issues(mut self, issues: Vec<ValidationIssue>) -> Self {
+        self.issues = issues;
+        self
     }
 }
 
 impl IntoRpcResponse for RpcError {
     fn into_rpc_response(self) -> RpcResponse {
         let status_code = self.status.to_http_status();
+        let error = self.clone();
 
         RpcResponse::new(
             status_code,
@@ -121,17 +216,54 @@ impl IntoRpcResponse for RpcError {
                 "error": {
                     "message": self.message,
                     "code": self.status.to_rpc_code(),
-                    "data": {
-                        "code": self.status.to_string(),
-                        "httpStatus": status_code.as_u16(),
-                    }
+                    "data": error_data(&self.status, self.details, &self.issues),
                 }
             }),
         )
+        .with_error(error)
     }
 }
 
-impl std::fmt::Display for RpcError {
+/// The `data` sent with errors when the router has no error formatter, which formatters can
+/// include in their own data with `#[serde(flatten)]`.
+#[derive(Clone, Debug, Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DefaultErrorData {
+    pub code: String,
+    pub http_status: u16,
+}
+
+impl DefaultErrorData {
+    pub fn new<D>(error: &RpcError<D>) -> Self {
+        Self {
+            code: error.status.to_string(),
+            http_status: error.status.to_http_status().as_u16(),
+        }
+    }
+}
+
+fn error_data(status: &RpcStatus, details: Option<Value>, issues: &[ValidationIssue]) -> Value {
+    let status_code = status.to_http_status();
+    let mut data = json!({
+        "code": status.to_string(),
+        "httpStatus": status_code.as_u16(),
+    });
+    if let Some(details) = details {
+        data["details"] = details;
+    }
+    if !issues.is_empty() {
+        data["issues"] = json!(issues);
+    }
+    data
+}
+
+impl IntoResponse for RpcError {
+    fn into_response(self) -> Response {
+        self.into_rpc_response().into_response()
+    }
+}
+
+impl<D> std::fmt::Display for RpcError<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -141,4 +273,91 @@ impl std::fmt::Display for RpcError {
     }
 }
 
-impl std::error::Error for RpcError {}
+impl<D: std::fmt::Debug> std::error::Error for RpcError<D> {}
+
+/// Errors that procedures can return, by converting them into an [`RpcError`].
+///
+/// Procedures may return `Result<T, E>` for any `E` implementing this, including aliases like
+/// `std::io::Result<T>`. Implement it for your own errors to use `?` with them in procedures.
+pub trait IntoRpcError {
+    fn into_rpc_error(self) -> RpcError;
+
+    /// The type of the error's details, exported to TypeScript.
+    fn details_type(_types: &mut TypeCollection) -> Option<Reference>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+impl<D: Serialize + specta::Type + 'static> IntoRpcError for RpcError<D> {
+    fn into_rpc_error(self) -> RpcError {
+        let details = match self.details.map(serde_json::to_value).transpose() {
+            Ok(details) => details,
+            Err(err) => {
+                return RpcError::new(
+                    RpcStatus::InternalServerError,
+                    format!("Failed to serialize error details: {}", err),
+                );
+            }
+        };
+
+        RpcError {
+            status: self.status,
+            message: self.message,
+            details,
+            issues: self.issues,
+        }
+    }
+
+    fn details_type(types: &mut TypeCollection) -> Option<Reference> {
+        // Untyped errors can carry any JSON, which isn't worth exporting
+        if TypeId::of::<D>() == TypeId::of::<Value>() {
+            return None;
+        }
+
+        Some(D::reference(types, &[]))
+    }
+}
+
+impl IntoRpcError for serde_json::Error {
+    fn into_rpc_error(self) -> RpcError {
+        RpcError::new(RpcStatus::InternalServerError, self.to_string())
+    }
+}
+
+impl IntoRpcError for std::io::Error {
+    fn into_rpc_error(self) -> RpcError {
+        use std::io::ErrorKind;
+
+        let status = match self.kind() {
+            ErrorKind::NotFound => RpcStatus::NotFound,
+            ErrorKind::PermissionDenied => RpcStatus::Forbidden,
+            ErrorKind::AlreadyExists => RpcStatus::Conflict,
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => RpcStatus::BadRequest,
+            ErrorKind::TimedOut => RpcStatus::Timeout,
+            _ => RpcStatus::InternalServerError,
+        };
+
+        RpcError::new(status, self.to_string())
+    }
+}
+
+/// The error of procedures that return a plain value, which never fail.
+impl IntoRpcError for std::convert::Infallible {
+    fn into_rpc_error(self) -> RpcError {
+        match self {}
+    }
+}
+
+/// Keeps the error if it is an [`RpcError`], anything else is an `INTERNAL_SERVER_ERROR`.
+#[cfg(feature = "anyhow")]
+impl IntoRpcError for anyhow::Error {
+    fn into_rpc_error(self) -> RpcError {
+        match self.downcast::<RpcError>() {
+            Ok(err) => err,
+            Err(err) => RpcError::new(RpcStatus::InternalServerError, err.to_string()),
+        }
+    }
+}
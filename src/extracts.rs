@@ -0,0 +1,198 @@
+//! Used by `#[rpc]` to handle its `#[extract]` arguments. Most extractors only read the request's
+//! parts, but [`FormData`] and [`ByteStream`] take its body, which becomes the procedure's input.
+//! Which kind an argument is can only be known from its type, not from how it's spelled, so the
+//! macro calls `kind()` through two references like it does for returns, see
+//! [`returns`](crate::returns).
+
+use crate::{
+    router::{InputFormat, RpcType},
+    upload::{ByteStream, FormData},
+};
+use specta::{TypeCollection, datatype::reference::Reference};
+use std::marker::PhantomData;
+
+/// Stands in for an `#[extract]` argument of type `T` when only the type is needed.
+pub struct Extracted<T>(PhantomData<fn() -> T>);
+
+impl<T> Extracted<T> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for Extracted<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extractors that read the request body rather than a JSON input.
+pub trait BodyExtractor: specta::Type {
+    const FORMAT: InputFormat;
+}
+
+impl BodyExtractor for FormData {
+    const FORMAT: InputFormat = InputFormat::FormData;
+}
+
+impl BodyExtractor for ByteStream {
+    const FORMAT: InputFormat = InputFormat::OctetStream;
+}
+
+/// The input of a procedure whose body is read by an extractor.
+pub struct BodyInput {
+    pub format: InputFormat,
+    pub input_type: Reference,
+}
+
+/// Returned by `kind()` for extractors that read the request body.
+#[derive(Clone, Copy)]
+pub struct BodyTag;
+
+impl BodyTag {
+    pub fn body<T: BodyExtractor>(
+        self,
+        _extracted: Extracted<T>,
+        types: &mut TypeCollection,
+    ) -> Option<BodyInput> {
+        Some(BodyInput {
+            format: T::FORMAT,
+            input_type: T::reference(types, &[]),
+        })
+    }
+}
+
+/// Returned by `kind()` for extractors that only read the request's parts.
+#[derive(Clone, Copy)]
+pub struct PartsTag;
+
+impl PartsTag {
+    pub fn body<T>(
+        self,
+        _extracted: Extracted<T>,
+        _types: &mut TypeCollection,
+    ) -> Option<BodyInput> {
+        None
+    }
+}
+
+pub trait BodyKind {
+    fn kind(&self) -> BodyTag {
+        BodyTag
+    }
+}
+
+impl<T: BodyExtractor> BodyKind for &Extracted<T> {}
+
+pub trait PartsKind {
+    fn kind(&self) -> PartsTag {
+        PartsTag
+    }
+}
+
+impl<T> PartsKind for Extracted<T> {}
+
+/// The body input of the procedure `name`, given what each of its `#[extract]` arguments reads.
+///
+/// # Panics
+///
+/// Panics if more than one argument reads the body, if the procedure also takes a JSON input,
+/// or if it isn't a mutation. The body is the whole input, and only mutations are sent one.
+pub fn body_input<const N: usize>(
+    name: &str,
+    rpc_type: RpcType,
+    has_input: bool,
+    extracted: [Option<BodyInput>; N],
+) -> Option<BodyInput> {
+    let mut bodies = extracted.into_iter().flatten();
+    let body = bodies.next()?;
+    if bodies.next().is_some() {
+        panic!(
+            "`{}` can only take one FormData or ByteStream argument",
+            name
+        );
+    }
+    if has_input {
+        panic!(
+            "`{}` takes a FormData or ByteStream argument, so it cannot take other input arguments",
+            name
+        );
+    }
+    if rpc_type != RpcType::Mutation {
+        panic!(
+            "`{}` takes a FormData or ByteStream argument, which only mutations support",
+            name
+        );
+    }
+    Some(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod user {
+        /// A type of the user's own that happens to share its name with juno's.
+        pub struct FormData;
+    }
+
+    type Upload = ByteStream;
+
+    /// What `#[rpc]` generates for an `#[extract]` argument of type `$ty`.
+    macro_rules! body {
+        ($ty:ty, $types:expr) => {{
+            let extracted = Extracted::<$ty>::new();
+            (&&extracted).kind().body(extracted, $types)
+        }};
+    }
+
+    fn format(extracted: Option<BodyInput>) -> Option<InputFormat> {
+        extracted.map(|body| body.format)
+    }
+
+    fn form_data(types: &mut TypeCollection) -> Option<BodyInput> {
+        body!(FormData, types)
+    }
+
+    #[test]
+    fn body_extractors_are_found_by_type() {
+        let types = &mut TypeCollection::default();
+        assert_eq!(format(body!(FormData, types)), Some(InputFormat::FormData));
+        assert_eq!(format(body!(Upload, types)), Some(InputFormat::OctetStream));
+        assert_eq!(format(body!(user::FormData, types)), None);
+    }
+
+    #[test]
+    fn body_input_is_the_only_input() {
+        let types = &mut TypeCollection::default();
+        let body = body_input("upload", RpcType::Mutation, false, [None, form_data(types)]);
+        assert_eq!(format(body), Some(InputFormat::FormData));
+        assert!(body_input("update", RpcType::Mutation, true, [None]).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "`upload` can only take one FormData or ByteStream argument")]
+    fn body_input_panics_for_two_bodies() {
+        let types = &mut TypeCollection::default();
+        body_input(
+            "upload",
+            RpcType::Mutation,
+            false,
+            [form_data(types), form_data(types)],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "so it cannot take other input arguments")]
+    fn body_input_panics_with_other_inputs() {
+        let types = &mut TypeCollection::default();
+        body_input("upload", RpcType::Mutation, true, [form_data(types)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "which only mutations support")]
+    fn body_input_panics_for_queries() {
+        let types = &mut TypeCollection::default();
+        body_input("upload", RpcType::Query, false, [form_data(types)]);
+    }
+}
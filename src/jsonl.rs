@@ -0,0 +1,75 @@
+use crate::{
+    response::RpcResponse,
+    transformer::{Transformer, TypeAnnotations},
+};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream, stream::FuturesUnordered};
+use serde_json::{Value, json};
+use std::convert::Infallible;
+
+/// The `trpc-accept` value sent by `httpBatchStreamLink`.
+const JSONL_ACCEPT: &str = "application/jsonl";
+
+// Chunk markers from tRPC's JSONL stream producer.
+const CHUNK_VALUE_TYPE_PROMISE: u8 = 0;
+const PROMISE_STATUS_FULFILLED: u8 = 0;
+
+pub(crate) fn accepts_jsonl(headers: &HeaderMap) -> bool {
+    headers
+        .get("trpc-accept")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == JSONL_ACCEPT)
+}
+
+/// Streams batch results in the format `httpBatchStreamLink` expects.
+///
+/// The first line is a head that declares every call as a pending promise, with the call's
+/// index doubling as its chunk id. Each following line resolves one of those promises as soon
+/// as its handler finishes, so a slow call never holds up the ones after it.
+///
+/// With a transformer, each line is encoded as a whole, as tRPC does.
+pub(crate) fn batch_stream_response<F>(calls: Vec<F>, transformer: Transformer) -> Response
+where
+    F: Future<Output = RpcResponse> + Send + 'static,
+{
+    let head: serde_json::Map<String, Value> = (0..calls.len())
+        .map(|index| {
+            (
+                index.to_string(),
+                json!([[0], [Value::Null, CHUNK_VALUE_TYPE_PROMISE, index]]),
+            )
+        })
+        .collect();
+
+    let pending: FuturesUnordered<_> = calls
+        .into_iter()
+        .enumerate()
+        .map(|(index, call)| async move { (index, call.await) })
+        .collect();
+
+    let chunks = pending.map(move |(index, result)| {
+        let chunk = json!([index, PROMISE_STATUS_FULFILLED, [[result.value()]]]);
+        transformer.serialize(chunk, &result.annotations().prefixed("2.0.0.result.data"))
+    });
+
+    let head = transformer.serialize(Value::Object(head), &TypeAnnotations::default());
+    let lines = stream::once(async move { head })
+        .chain(chunks)
+        .map(|line| Ok::<_, Infallible>(format!("{line}\n")));
+
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (header::VARY, HeaderValue::from_static("trpc-accept")),
+        ],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
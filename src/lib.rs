@@ -1,4 +1,21 @@
+pub mod auth;
+pub mod context;
 pub mod errors;
+#[doc(hidden)]
+pub mod extracts;
+mod jsonl;
+pub mod middleware;
 pub mod response;
+#[doc(hidden)]
+pub mod returns;
 pub mod router;
+mod sse;
+pub mod subscription;
+pub mod transformer;
+pub mod upload;
+pub mod validation;
+#[cfg(feature = "ws")]
+mod ws;
+#[cfg(debug_assertions)]
+mod zod;
 pub use juno_macros::rpc;
@@ -0,0 +1,157 @@
+use crate::{
+    context::Ctx,
+    errors::RpcError,
+    response::{IntoRpcResponse, ResponseMeta, RpcResponse},
+    router::{RpcFuture, RpcHandler, RpcType},
+};
+use axum::http::request::Parts;
+use serde_json::Value;
+use std::{pin::Pin, sync::Arc};
+
+pub type MiddlewareFuture = Pin<Box<dyn Future<Output = Result<RpcResponse, RpcError>> + Send>>;
+
+/// Wraps the execution of procedures, like `.use()` in tRPC.
+///
+/// Middleware can inspect or change the call before passing it on with [`Next::run`], return
+/// an error instead of running the procedure, or change the response it gets back. Add it to
+/// every procedure in a router with [`RpcRouter::middleware`](crate::router::RpcRouter::middleware),
+/// which also covers namespaces when that router is nested, or to a single procedure with
+/// [`RpcMethod::with_middleware`](crate::router::RpcMethod::with_middleware).
+///
+/// Closures taking an [`RpcCall`] and [`Next`] can be used as middleware.
+pub trait Middleware<S>: Send + Sync + 'static {
+    fn handle(&self, call: RpcCall<S>, next: Next<S>) -> MiddlewareFuture;
+}
+
+impl<S, F, Fut> Middleware<S> for F
+where
+    F: Fn(RpcCall<S>, Next<S>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<RpcResponse, RpcError>> + Send + 'static,
+{
+    fn handle(&self, call: RpcCall<S>, next: Next<S>) -> MiddlewareFuture {
+        Box::pin(self(call, next))
+    }
+}
+
+/// A call to a procedure, as seen by [`Middleware`].
+pub struct RpcCall<S> {
+    /// The procedure's full path, e.g. `user.get` for procedures in nested routers.
+    pub path: String,
+    pub rpc_type: RpcType,
+    /// Permissions the procedure requires, see
+    /// [`RpcRouter::permission_resolver`](crate::router::RpcRouter::permission_resolver).
+    pub requires: &'static [&'static str],
+    /// The input as sent by the client, before it is deserialized for the procedure.
+    pub input: Option<Value>,
+    pub state: S,
+    pub parts: Parts,
+}
+
+impl<S> RpcCall<S> {
+    /// The context of type `T` for this call, if one has been set.
+    pub fn context<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        Ctx::from_parts(&self.parts)
+    }
+
+    pub fn context_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.parts
+            .extensions
+            .get_mut::<Ctx<T>>()
+            .map(|ctx| &mut ctx.0)
+    }
+
+    /// The handle for adding headers and a status to the HTTP response.
+    pub fn response_meta(&self) -> ResponseMeta {
+        ResponseMeta::from_parts(&self.parts)
+    }
+
+    /// Sets the context of type `T` for the rest of the call, replacing any previous context of
+    /// that type. Procedures receive it through a [`Ctx<T>`] argument.
+    pub fn set_context<T: Clone + Send + Sync + 'static>(&mut self, ctx: T) {
+        self.parts.extensions.insert(Ctx(ctx));
+    }
+}
+
+/// The rest of the middleware chain, ending with the procedure itself.
+pub struct Next<S> {
+    run: Box<dyn FnOnce(RpcCall<S>) -> RpcFuture + Send>,
+}
+
+impl<S> Next<S> {
+    fn new(run: impl FnOnce(RpcCall<S>) -> RpcFuture + Send + 'static) -> Self {
+        Self { run: Box::new(run) }
+    }
+
+    /// Runs the rest of the chain with `call`.
+    pub async fn run(self, call: RpcCall<S>) -> RpcResponse {
+        (self.run)(call).await
+    }
+
+    fn run_boxed(self, call: RpcCall<S>) -> RpcFuture {
+        (self.run)(call)
+    }
+}
+
+/// Runs `call` through each of `middleware` in order, then `handler`.
+pub(crate) fn run_chain<S: Send + 'static>(
+    middleware: Vec<Arc<dyn Middleware<S>>>,
+    handler: RpcHandler<S>,
+    call: RpcCall<S>,
+) -> RpcFuture {
+    chain(middleware.into_iter(), handler).run_boxed(call)
+}
+
+fn chain<S: Send + 'static>(
+    mut middleware: std::vec::IntoIter<Arc<dyn Middleware<S>>>,
+    handler: RpcHandler<S>,
+) -> Next<S> {
+    Next::new(move |call| match middleware.next() {
+        Some(current) => {
+            let next = chain(middleware, handler);
+            Box::pin(async move {
+                current
+                    .handle(call, next)
+                    .await
+                    .unwrap_or_else(IntoRpcResponse::into_rpc_response)
+            })
+        }
+        None => handler(call.input, call.state, call.parts),
+    })
+}
+
+/// Runs middleware written for state `S` in a router with state `S2`.
+pub(crate) struct MapState<S, S2> {
+    pub(crate) middleware: Arc<dyn Middleware<S>>,
+    pub(crate) to_state: Arc<dyn Fn(&S2) -> S + Send + Sync>,
+}
+
+impl<S, S2> Middleware<S2> for MapState<S, S2>
+where
+    S: Send + 'static,
+    S2: Clone + Send + 'static,
+{
+    fn handle(&self, call: RpcCall<S2>, next: Next<S2>) -> MiddlewareFuture {
+        // The rest of the chain carries on with the router's own state
+        let state = call.state.clone();
+        let next = Next::new(move |call: RpcCall<S>| {
+            next.run_boxed(RpcCall {
+                path: call.path,
+                rpc_type: call.rpc_type,
+                requires: call.requires,
+                input: call.input,
+                state,
+                parts: call.parts,
+            })
+        });
+
+        let call = RpcCall {
+            state: (self.to_state)(&call.state),
+            path: call.path,
+            rpc_type: call.rpc_type,
+            requires: call.requires,
+            input: call.input,
+            parts: call.parts,
+        };
+        self.middleware.handle(call, next)
+    }
+}
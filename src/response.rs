@@ -1,27 +1,415 @@
-use crate::errors::{RpcError, RpcStatus};
-use axum::{Json, http::StatusCode, response::IntoResponse};
+use crate::{
+    errors::{IntoRpcError, RpcError, RpcStatus},
+    router::OutputFormat,
+    sse,
+    transformer::{self, Transformer, TypeAnnotations},
+};
+use axum::{
+    BoxError, Json,
+    body::{Body, Bytes},
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use futures_util::{Stream, StreamExt, TryStream};
 use serde::Serialize;
 use serde_json::Value;
+use specta::{
+    Generics, TypeCollection,
+    datatype::{DataType, GenericType, reference::Reference},
+};
+use std::{
+    any::TypeId,
+    borrow::Cow,
+    convert::Infallible,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+};
 
-pub struct RpcResponse(StatusCode, Value);
+pub type RpcEventStream = Pin<Box<dyn Stream<Item = Result<RpcEvent, RpcError>> + Send>>;
+
+pub struct RpcResponse {
+    status: StatusCode,
+    body: RpcBody,
+    /// Annotations for `result.data`, used when the router has a [`Transformer`].
+    annotations: TypeAnnotations,
+    /// The error the response was created from, for the router's error formatter.
+    error: Option<Box<RpcError>>,
+}
+
+enum RpcBody {
+    Json(Value),
+    Events(RpcEventStream),
+    Binary(Binary),
+}
 
 impl RpcResponse {
     pub fn new(status: StatusCode, value: Value) -> Self {
-        Self(status, value)
+        Self {
+            status,
+            body: RpcBody::Json(value),
+            annotations: TypeAnnotations::default(),
+            error: None,
+        }
+    }
+
+    /// Creates the response for a subscription, which is sent to the client as one event per
+    /// item in `stream`.
+    pub fn subscription<St>(stream: St) -> Self
+    where
+        St: Stream + Send + 'static,
+        St::Item: IntoRpcEvent,
+    {
+        Self {
+            status: StatusCode::OK,
+            body: RpcBody::Events(Box::pin(stream.map(IntoRpcEvent::into_rpc_event))),
+            annotations: TypeAnnotations::default(),
+            error: None,
+        }
+    }
+
+    /// Like [`RpcResponse::subscription`], but an `Err` item is sent as an error and ends the
+    /// subscription.
+    pub fn try_subscription<St>(stream: St) -> Self
+    where
+        St: Stream + Send + 'static,
+        St::Item: IntoRpcResult,
+        <St::Item as IntoRpcResult>::Ok: IntoRpcEvent,
+    {
+        Self {
+            status: StatusCode::OK,
+            body: RpcBody::Events(Box::pin(stream.map(|item| {
+                item.into_rpc_result()
+                    .map_err(IntoRpcError::into_rpc_error)
+                    .and_then(IntoRpcEvent::into_rpc_event)
+            }))),
+            annotations: TypeAnnotations::default(),
+            error: None,
+        }
     }
 
     pub fn status(&self) -> StatusCode {
-        self.0
+        self.status
+    }
+
+    /// The JSON body of the response, or `None` for subscriptions and binary responses.
+    pub fn value(&self) -> Option<&Value> {
+        match &self.body {
+            RpcBody::Json(value) => Some(value),
+            RpcBody::Events(_) | RpcBody::Binary(_) => None,
+        }
+    }
+
+    /// The error the response was created from, if it is an error response.
+    pub fn error(&self) -> Option<&RpcError> {
+        self.error.as_deref()
+    }
+
+    pub(crate) fn with_error(mut self, error: RpcError) -> Self {
+        self.error = Some(Box::new(error));
+        self
+    }
+
+    /// Replaces `error.data` in an error response's body.
+    pub(crate) fn set_error_data(&mut self, data: Value) {
+        if let RpcBody::Json(body) = &mut self.body
+            && let Some(Value::Object(error)) = body.get_mut("error")
+        {
+            error.insert("data".to_string(), data);
+        }
+    }
+
+    pub(crate) fn annotations(&self) -> &TypeAnnotations {
+        &self.annotations
     }
 
-    pub fn value(&self) -> &Value {
-        &self.1
+    /// The JSON body of the response encoded with `transformer`, or `None` for subscriptions.
+    pub(crate) fn encoded_value(&self, transformer: Transformer) -> Option<Value> {
+        self.value()
+            .map(|value| transformer.serialize_envelope(value, &self.annotations))
+    }
+
+    /// Takes the event stream out of a subscription response, or returns the response unchanged
+    /// if it has a JSON body (e.g. the subscription failed before it started).
+    pub fn into_events(self) -> Result<RpcEventStream, Self> {
+        match self.body {
+            RpcBody::Events(events) => Ok(events),
+            body => Err(Self { body, ..self }),
+        }
     }
 }
 
 impl IntoResponse for RpcResponse {
     fn into_response(self) -> axum::response::Response {
-        (self.0, Json(self.1)).into_response()
+        match self.body {
+            RpcBody::Json(value) => (self.status, Json(value)).into_response(),
+            RpcBody::Events(events) => {
+                sse::event_stream_response(events, Transformer::None, |err| err.into_rpc_response())
+            }
+            RpcBody::Binary(binary) => {
+                let mut response = (self.status, binary.body).into_response();
+                let headers = response.headers_mut();
+                if let Ok(content_type) = HeaderValue::from_str(&binary.content_type) {
+                    headers.insert(header::CONTENT_TYPE, content_type);
+                }
+                if let Some(disposition) = binary
+                    .disposition
+                    .and_then(|disposition| HeaderValue::from_str(&disposition).ok())
+                {
+                    headers.insert(header::CONTENT_DISPOSITION, disposition);
+                }
+                response
+            }
+        }
+    }
+}
+
+/// Raw bytes sent as the response body instead of JSON, for downloads such as exported files
+/// or generated images.
+///
+/// tRPC links expect JSON, so clients should fetch these procedures directly, e.g.
+/// `GET /trpc/export_users?input=...`. The generated client types them as a `ReadableStream`.
+pub struct Binary {
+    body: Body,
+    content_type: String,
+    disposition: Option<String>,
+}
+
+impl Binary {
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self::from_body(Body::from(bytes.into()))
+    }
+
+    /// Streams the response body from `stream` rather than holding all of it in memory.
+    pub fn from_stream<St>(stream: St) -> Self
+    where
+        St: TryStream + Send + 'static,
+        St::Ok: Into<Bytes>,
+        St::Error: Into<BoxError>,
+    {
+        Self::from_body(Body::from_stream(stream))
+    }
+
+    fn from_body(body: Body) -> Self {
+        Self {
+            body,
+            content_type: "application/octet-stream".to_string(),
+            disposition: None,
+        }
+    }
+
+    /// Sets the `Content-Type` header. Defaults to `application/octet-stream`.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = content_type.into();
+        self
+    }
+
+    /// Asks browsers to download the response as `file_name`.
+    pub fn attachment(mut self, file_name: &str) -> Self {
+        self.disposition = Some(content_disposition("attachment", file_name));
+        self
+    }
+
+    /// Asks browsers to display the response, using `file_name` if it is saved.
+    pub fn inline(mut self, file_name: &str) -> Self {
+        self.disposition = Some(content_disposition("inline", file_name));
+        self
+    }
+}
+
+impl specta::Type for Binary {
+    fn inline(_type_map: &mut TypeCollection, _generics: Generics) -> DataType {
+        DataType::Generic(GenericType::from(Cow::Borrowed(
+            "ReadableStream<Uint8Array>",
+        )))
+    }
+}
+
+/// Formats a `Content-Disposition` header, adding an RFC 5987 `filename*` for names that
+/// cannot be sent as plain ASCII.
+fn content_disposition(disposition: &str, file_name: &str) -> String {
+    let ascii_name: String = file_name
+        .chars()
+        .map(|char| match char {
+            ' '..='~' if char != '"' && char != '\\' => char,
+            _ => '_',
+        })
+        .collect();
+
+    let mut value = format!("{disposition}; filename=\"{ascii_name}\"");
+    if ascii_name != file_name {
+        value.push_str("; filename*=UTF-8''");
+        for byte in file_name.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => value.push(byte as char),
+                b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|'
+                | b'~' => value.push(byte as char),
+                byte => value.push_str(&format!("%{byte:02X}")),
+            }
+        }
+    }
+
+    value
+}
+
+/// Headers and a status to add to the HTTP response, like tRPC's `responseMeta`.
+///
+/// Add it as an `#[extract]` argument to a procedure, or get it in middleware with
+/// [`RpcCall::response_meta`](crate::middleware::RpcCall::response_meta). Every call in a batch
+/// shares the same response, so they also share the meta. Changes are only applied if they are
+/// made before the response starts, so not after the first result of a streamed batch or
+/// subscription, and they are ignored over WebSocket.
+#[derive(Clone, Default)]
+pub struct ResponseMeta(Arc<Mutex<MetaState>>);
+
+#[derive(Default)]
+struct MetaState {
+    status: Option<StatusCode>,
+    headers: HeaderMap,
+}
+
+impl ResponseMeta {
+    /// Replaces the status of a successful response, e.g. with `201 Created`. Errors keep
+    /// their own status.
+    pub fn set_status(&self, status: StatusCode) {
+        self.state().status = Some(status);
+    }
+
+    /// Sets a header, replacing any previous value.
+    pub fn insert_header(&self, name: HeaderName, value: HeaderValue) {
+        self.state().headers.insert(name, value);
+    }
+
+    /// Adds a header, keeping any previous values.
+    pub fn append_header(&self, name: HeaderName, value: HeaderValue) {
+        self.state().headers.append(name, value);
+    }
+
+    /// Adds a `Set-Cookie` header, e.g. `session=abc; HttpOnly; Secure; Path=/`.
+    pub fn set_cookie(&self, cookie: HeaderValue) {
+        self.append_header(header::SET_COOKIE, cookie);
+    }
+
+    pub(crate) fn from_parts(parts: &Parts) -> Self {
+        parts.extensions.get::<Self>().cloned().unwrap_or_default()
+    }
+
+    /// Adds the headers and status set so far to `response`.
+    pub(crate) fn apply(&self, mut response: Response) -> Response {
+        let state = self.state();
+        if let Some(status) = state.status
+            && response.status().is_success()
+        {
+            *response.status_mut() = status;
+        }
+
+        let mut current = None;
+        for (name, value) in state.headers.iter() {
+            // Set-Cookie and friends may have been added more than once
+            if current.as_ref() != Some(name) {
+                response.headers_mut().remove(name);
+                current = Some(name.clone());
+            }
+            response.headers_mut().append(name, value.clone());
+        }
+
+        response
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MetaState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ResponseMeta {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
+
+/// A single item sent to a subscriber.
+pub struct RpcEvent {
+    /// The event id for [`Tracked`](crate::subscription::Tracked) items.
+    pub id: Option<String>,
+    pub data: Value,
+    /// Type annotations for `data`, see [`transformer::to_value`].
+    pub annotations: TypeAnnotations,
+}
+
+pub trait IntoRpcEvent {
+    /// The type the client receives, used when generating the client.
+    type Output: specta::Type;
+    /// Whether events carry an id the client can resume from.
+    const TRACKED: bool = false;
+
+    fn into_rpc_event(self) -> Result<RpcEvent, RpcError>;
+}
+
+impl<T: Serialize + specta::Type> IntoRpcEvent for T {
+    type Output = T;
+
+    fn into_rpc_event(self) -> Result<RpcEvent, RpcError> {
+        match transformer::to_value(&self) {
+            Ok((data, annotations)) => Ok(RpcEvent {
+                id: None,
+                data,
+                annotations,
+            }),
+            Err(_) => Err(RpcError::new(
+                RpcStatus::InternalServerError,
+                "Failed to serialize subscription event".to_string(),
+            )),
+        }
+    }
+}
+
+/// The result of a procedure: any `Result<T, E>` whose error implements [`IntoRpcError`],
+/// including aliases like `anyhow::Result<T>` or `type ApiResult<T> = Result<T, MyError>`.
+/// Procedures returning anything else succeed with that value.
+pub trait IntoRpcResult {
+    type Ok;
+    type Err: IntoRpcError;
+
+    fn into_rpc_result(self) -> Result<Self::Ok, Self::Err>;
+}
+
+impl<T, E: IntoRpcError> IntoRpcResult for Result<T, E> {
+    type Ok = T;
+    type Err = E;
+
+    fn into_rpc_result(self) -> Result<T, E> {
+        self
+    }
+}
+
+/// Values a query or mutation can succeed with: anything serializable, or [`Binary`].
+pub trait IntoRpcOutput {
+    /// How the value is sent in the response body.
+    const FORMAT: OutputFormat;
+
+    /// The type the client receives, or `None` for procedures returning `()`.
+    fn output_type(types: &mut TypeCollection) -> Option<Reference>;
+}
+
+impl<T: Serialize + specta::Type + 'static> IntoRpcOutput for T {
+    const FORMAT: OutputFormat = OutputFormat::Json;
+
+    fn output_type(types: &mut TypeCollection) -> Option<Reference> {
+        if TypeId::of::<T>() == TypeId::of::<()>() {
+            return None;
+        }
+
+        Some(T::reference(types, &[]))
+    }
+}
+
+impl IntoRpcOutput for Binary {
+    const FORMAT: OutputFormat = OutputFormat::Binary;
+
+    fn output_type(types: &mut TypeCollection) -> Option<Reference> {
+        Some(<Binary as specta::Type>::reference(types, &[]))
     }
 }
 
@@ -29,12 +417,26 @@ pub trait IntoRpcResponse {
     fn into_rpc_response(self) -> RpcResponse;
 }
 
+impl IntoRpcResponse for Result<Binary, RpcError> {
+    fn into_rpc_response(self) -> RpcResponse {
+        match self {
+            Err(err) => err.into_rpc_response(),
+            Ok(binary) => RpcResponse {
+                status: StatusCode::OK,
+                body: RpcBody::Binary(binary),
+                annotations: TypeAnnotations::default(),
+                error: None,
+            },
+        }
+    }
+}
+
 impl<T: Serialize> IntoRpcResponse for Result<T, RpcError> {
     fn into_rpc_response(self) -> RpcResponse {
         match self {
             Err(err) => err.into_rpc_response(),
             Ok(value) => {
-                let Ok(serialized) = serde_json::to_value(value) else {
+                let Ok((serialized, annotations)) = transformer::to_value(&value) else {
                     return RpcError::new(
                         RpcStatus::InternalServerError,
                         "Failed to serialize response".to_string(),
@@ -42,14 +444,17 @@ impl<T: Serialize> IntoRpcResponse for Result<T, RpcError> {
                     .into_rpc_response();
                 };
 
-                RpcResponse::new(
-                    StatusCode::OK,
-                    serde_json::json!({
-                        "result": {
-                            "data": serialized
-                        }
-                    }),
-                )
+                RpcResponse {
+                    annotations,
+                    ..RpcResponse::new(
+                        StatusCode::OK,
+                        serde_json::json!({
+                            "result": {
+                                "data": serialized
+                            }
+                        }),
+                    )
+                }
             }
         }
     }
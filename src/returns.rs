@@ -0,0 +1,152 @@
+//! Used by `#[rpc]` to handle whatever a procedure returns. Procedures can return a
+//! `Result<T, E>` under any name, e.g. `anyhow::Result<T>` or a local alias, or a plain `T`.
+//! Which one it is can only be known from the type itself, so the macro calls `kind()` through
+//! two references and lets method resolution pick the first trait that applies.
+
+use crate::{
+    errors::IntoRpcError,
+    response::{IntoRpcEvent, IntoRpcOutput, IntoRpcResult, RpcResponse},
+    router::OutputFormat,
+};
+use futures_util::Stream;
+use specta::{TypeCollection, datatype::reference::Reference};
+use std::{convert::Infallible, future::Future, marker::PhantomData};
+
+/// Stands in for a value of type `R` when only the type is needed.
+pub struct Returned<R>(PhantomData<fn() -> R>);
+
+impl<R> Returned<R> {
+    /// The type a procedure returns, given a closure calling it that is never run.
+    pub fn of<F, Fut>(_call: F) -> Self
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = R>,
+    {
+        Self(PhantomData)
+    }
+
+    pub fn of_value(_value: &R) -> Self {
+        Self(PhantomData)
+    }
+
+    /// The type of the items of a subscription's stream.
+    pub fn items_of<St: Stream<Item = R>>(_stream: &St) -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T, E: IntoRpcError> Returned<Result<T, E>> {
+    pub fn output_type(&self, types: &mut TypeCollection) -> Option<Reference>
+    where
+        T: IntoRpcOutput,
+    {
+        T::output_type(types)
+    }
+
+    pub fn output_format(&self) -> OutputFormat
+    where
+        T: IntoRpcOutput,
+    {
+        T::FORMAT
+    }
+
+    pub fn error_type(&self, types: &mut TypeCollection) -> Option<Reference> {
+        E::details_type(types)
+    }
+
+    /// The items of a subscription's stream.
+    pub fn items(&self) -> Returned<T::Item>
+    where
+        T: Stream,
+    {
+        Returned(PhantomData)
+    }
+
+    pub fn event_type(&self, types: &mut TypeCollection) -> Reference
+    where
+        T: IntoRpcEvent,
+    {
+        <T::Output as specta::Type>::reference(types, &[])
+    }
+
+    pub fn tracked(&self) -> bool
+    where
+        T: IntoRpcEvent,
+    {
+        T::TRACKED
+    }
+}
+
+/// Returned by `kind()` for results.
+#[derive(Clone, Copy)]
+pub struct ResultTag;
+
+impl ResultTag {
+    pub fn wrap<R: IntoRpcResult>(self, returned: R) -> Result<R::Ok, R::Err> {
+        returned.into_rpc_result()
+    }
+
+    pub fn probe<R: IntoRpcResult>(
+        self,
+        _returned: Returned<R>,
+    ) -> Returned<Result<R::Ok, R::Err>> {
+        Returned(PhantomData)
+    }
+
+    pub fn subscription<St>(self, stream: St) -> RpcResponse
+    where
+        St: Stream + Send + 'static,
+        St::Item: IntoRpcResult,
+        <St::Item as IntoRpcResult>::Ok: IntoRpcEvent,
+    {
+        RpcResponse::try_subscription(stream)
+    }
+}
+
+/// Returned by `kind()` for plain values, which are treated as results that never fail.
+#[derive(Clone, Copy)]
+pub struct ValueTag;
+
+impl ValueTag {
+    pub fn wrap<T>(self, returned: T) -> Result<T, Infallible> {
+        Ok(returned)
+    }
+
+    pub fn probe<T>(self, _returned: Returned<T>) -> Returned<Result<T, Infallible>> {
+        Returned(PhantomData)
+    }
+
+    pub fn subscription<St>(self, stream: St) -> RpcResponse
+    where
+        St: Stream + Send + 'static,
+        St::Item: IntoRpcEvent,
+    {
+        RpcResponse::subscription(stream)
+    }
+}
+
+pub trait ResultKind {
+    fn kind(&self) -> ResultTag {
+        ResultTag
+    }
+}
+
+impl<R: IntoRpcResult> ResultKind for &Returned<R> {}
+
+/// Any other `Result` is still a result, so an error type that doesn't implement
+/// `IntoRpcError` is reported as such rather than as the result not being serializable.
+pub trait ResultLikeKind {
+    fn kind(&self) -> ResultTag {
+        ResultTag
+    }
+}
+
+impl<T, E> ResultLikeKind for &&Returned<Result<T, E>> {}
+
+pub trait ValueKind {
+    fn kind(&self) -> ValueTag {
+        ValueTag
+    }
+}
+
+impl<R> ValueKind for Returned<R> {}
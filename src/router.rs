@@ -1,34 +1,78 @@
+#[cfg(debug_assertions)]
+use crate::zod::{self, ZodExporter};
 use crate::{
+    auth::{self, Authenticator, Identity, PermissionResolver},
+    context::Ctx,
     errors::{RpcError, RpcStatus},
-    response::{IntoRpcResponse, RpcResponse},
+    jsonl,
+    middleware::{MapState, Middleware, Next, RpcCall},
+    response::{IntoRpcResponse, ResponseMeta, RpcResponse},
+    sse,
+    subscription::LastEventId,
+    transformer::Transformer,
+    upload::{ByteStream, FormData, PendingBody},
+    validation::{Rule, ValidationIssue},
 };
 use axum::{
-    Router,
+    Json, Router,
     body::Body,
-    extract::{FromRequestParts, Query, Request, State},
-    http::{Method, request::Parts},
-    response::IntoResponse,
+    extract::{FromRef, Query, Request, State},
+    http::{Extensions, Method, StatusCode, request::Parts},
+    response::{IntoResponse, Response},
     routing::any,
 };
+use futures_util::future::join_all;
+use heck::ToPascalCase;
 use indoc::formatdoc;
 use serde::{
-    Deserialize, Deserializer,
+    Deserialize, Deserializer, Serialize,
     de::{self},
 };
 use serde_json::Value;
 use specta::{
-    TypeCollection,
-    datatype::{FunctionResultVariant, reference::Reference},
+    Type, TypeCollection,
+    datatype::{
+        DataType, FunctionResultVariant, StructFields,
+        reference::{self, Reference},
+    },
 };
 use specta_typescript::{BigIntExportBehavior, Typescript, datatype};
-use std::{collections::HashMap, marker::PhantomData, pin::Pin, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+};
 
 const MAX_BODY_SIZE: usize = 1024 * 1024; // 1 MB
+const MAX_QUERY_SIZE: usize = 16 * 1024; // 16 KB
+const DEFAULT_MAX_BATCH_SIZE: usize = 50;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RpcType {
     Query,
     Mutation,
+    Subscription,
+}
+
+/// How a procedure's input is sent in a request body.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Json,
+    /// `multipart/form-data`, read through a [`FormData`] argument.
+    FormData,
+    /// `application/octet-stream`, read through a [`ByteStream`] argument.
+    OctetStream,
+}
+
+/// How a procedure's result is sent in the response body.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    /// Raw bytes from a [`Binary`](crate::response::Binary) result.
+    Binary,
 }
 
 pub struct RpcMethod<S> {
@@ -36,67 +80,491 @@ pub struct RpcMethod<S> {
     pub rpc_type: RpcType,
     pub input_type: Option<Reference>,
     pub output_type: Option<Reference>,
-    pub handler: Arc<
-        dyn Fn(Option<Value>, S, Parts) -> Pin<Box<dyn Future<Output = RpcResponse> + Send>>
-            + Send
-            + Sync,
-    >,
+    /// The type of the details in the procedure's errors, see [`RpcError`].
+    pub error_type: Option<Reference>,
+    /// Whether a subscription yields [`Tracked`](crate::subscription::Tracked) events.
+    pub tracked: bool,
+    pub input_format: InputFormat,
+    pub output_format: OutputFormat,
+    /// The largest request body accepted, in bytes. Defaults to 1 MB.
+    pub max_body_size: Option<usize>,
+    /// Whether the procedure requires an identity from the router's [`Authenticator`].
+    pub auth: bool,
+    /// Permissions the caller's identity must have, checked by the router's
+    /// [`PermissionResolver`].
+    pub requires: &'static [&'static str],
+    /// The `#[validate(..)]` rules on each input field.
+    pub rules: &'static [(&'static str, Rule)],
+    /// Middleware run around this procedure only, after the router's.
+    pub middleware: Vec<Arc<dyn Middleware<S>>>,
+    pub handler: RpcHandler<S>,
+}
+
+impl<S: Send + 'static> RpcMethod<S> {
+    /// Whether both the input and result are JSON, which batches and WebSockets require.
+    pub(crate) fn uses_json(&self) -> bool {
+        self.input_format == InputFormat::Json && self.output_format == OutputFormat::Json
+    }
+
+    /// Runs `middleware` around this procedure, after any already added. Use it when adding the
+    /// procedure, e.g. `.add(|types| get_user(types).with_middleware(log))`.
+    pub fn with_middleware(mut self, middleware: impl Middleware<S>) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Adapts the procedure to a router with state `S2`, handing it the state `to_state` derives.
+    fn map_state<S2: Clone + Send + 'static>(
+        self,
+        to_state: impl Fn(&S2) -> S + Send + Sync + 'static,
+    ) -> RpcMethod<S2> {
+        let to_state: Arc<dyn Fn(&S2) -> S + Send + Sync> = Arc::new(to_state);
+        let middleware = map_middleware(self.middleware, &to_state);
+        let handler = self.handler;
+        RpcMethod {
+            name: self.name,
+            rpc_type: self.rpc_type,
+            input_type: self.input_type,
+            output_type: self.output_type,
+            error_type: self.error_type,
+            tracked: self.tracked,
+            input_format: self.input_format,
+            output_format: self.output_format,
+            max_body_size: self.max_body_size,
+            auth: self.auth,
+            requires: self.requires,
+            rules: self.rules,
+            middleware,
+            handler: Arc::new(move |input, state, parts| handler(input, to_state(&state), parts)),
+        }
+    }
+}
+
+fn map_middleware<S, S2>(
+    middleware: Vec<Arc<dyn Middleware<S>>>,
+    to_state: &Arc<dyn Fn(&S2) -> S + Send + Sync>,
+) -> Vec<Arc<dyn Middleware<S2>>>
+where
+    S: Send + 'static,
+    S2: Clone + Send + 'static,
+{
+    middleware
+        .into_iter()
+        .map(|middleware| map_one(middleware, to_state))
+        .collect()
 }
 
+fn map_one<S, S2>(
+    middleware: Arc<dyn Middleware<S>>,
+    to_state: &Arc<dyn Fn(&S2) -> S + Send + Sync>,
+) -> Arc<dyn Middleware<S2>>
+where
+    S: Send + 'static,
+    S2: Clone + Send + 'static,
+{
+    Arc::new(MapState {
+        middleware,
+        to_state: to_state.clone(),
+    })
+}
+
+pub type RpcHandler<S> = Arc<dyn Fn(Option<Value>, S, Parts) -> RpcFuture + Send + Sync>;
+
+pub type RpcFuture = Pin<Box<dyn Future<Output = RpcResponse> + Send>>;
+
+type ContextFactory<S> = Arc<
+    dyn Fn(S, Parts) -> Pin<Box<dyn Future<Output = Result<Extensions, RpcError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+type ErrorFormatter =
+    Arc<dyn Fn(&RpcError, &str, RpcType, Option<&Value>) -> Option<Value> + Send + Sync>;
+
 pub struct RpcRouter<S = ()> {
-    handlers: Arc<HashMap<String, RpcMethod<S>>>,
+    handlers: HashMap<String, RpcMethod<S>>,
+    context: Option<ContextFactory<S>>,
+    authenticator: Option<Arc<dyn Middleware<S>>>,
+    permission_resolver: Option<Arc<dyn Middleware<S>>>,
+    middleware: Vec<Arc<dyn Middleware<S>>>,
     type_map: TypeCollection,
+    max_batch_size: usize,
+    allow_method_override: bool,
+    zod_schemas: bool,
+    pub(crate) transformer: Transformer,
+    error_formatter: Option<ErrorFormatter>,
+    error_data_type: Option<Reference>,
     _phantom: PhantomData<S>,
 }
 
 impl RpcRouter<()> {
     pub fn new() -> Self {
+        // Any procedure with an input can fail with issues, so the client always needs the type
+        let mut type_map = TypeCollection::default();
+        type_map.register::<ValidationIssue>();
+
         Self {
-            handlers: Arc::new(HashMap::new()),
-            type_map: TypeCollection::default(),
+            handlers: HashMap::new(),
+            context: None,
+            authenticator: None,
+            permission_resolver: None,
+            middleware: Vec::new(),
+            type_map,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            allow_method_override: false,
+            zod_schemas: false,
+            transformer: Transformer::None,
+            error_formatter: None,
+            error_data_type: None,
             _phantom: PhantomData,
         }
     }
 }
 
-impl<S1: Clone + Send + Sync + 'static> RpcRouter<S1> {
+impl Default for RpcRouter<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RpcRouter<()> {
+    /// Turns the router into one with state `S2`, keeping the procedures already added.
     pub fn for_state<S2: Clone + Send + Sync + 'static>(self) -> RpcRouter<S2> {
+        let to_state: Arc<dyn Fn(&S2) + Send + Sync> = Arc::new(|_| ());
         RpcRouter {
-            handlers: Arc::new(HashMap::new()),
+            handlers: self
+                .handlers
+                .into_iter()
+                .map(|(name, method)| (name, method.map_state(|_| ())))
+                .collect(),
+            context: self.context.map(|context| -> ContextFactory<S2> {
+                Arc::new(move |_, parts| context((), parts))
+            }),
+            authenticator: self
+                .authenticator
+                .map(|authenticator| map_one(authenticator, &to_state)),
+            permission_resolver: self
+                .permission_resolver
+                .map(|resolver| map_one(resolver, &to_state)),
+            middleware: map_middleware(self.middleware, &to_state),
             type_map: self.type_map,
+            max_batch_size: self.max_batch_size,
+            allow_method_override: self.allow_method_override,
+            zod_schemas: self.zod_schemas,
+            transformer: self.transformer,
+            error_formatter: self.error_formatter,
+            error_data_type: self.error_data_type,
             _phantom: PhantomData::<S2>,
         }
     }
+}
 
+impl<S1: Clone + Send + Sync + 'static> RpcRouter<S1> {
+    /// Adds a procedure defined with `#[rpc]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a procedure with the same path was already added, if the path is that of a
+    /// namespace from [`nest`](Self::nest), or if a dotted prefix of the path is a procedure.
+    #[allow(clippy::should_implement_trait)]
     pub fn add<F>(mut self, route_handler_wrapper: F) -> Self
     where
         F: FnOnce(&mut TypeCollection) -> RpcMethod<S1>,
     {
         let route_handler = route_handler_wrapper(&mut self.type_map);
-        let handlers = Arc::get_mut(&mut self.handlers).unwrap();
-        handlers.insert(route_handler.name.clone(), route_handler);
+        let name = route_handler.name.clone();
+        self.check_path_free(&name, "add");
+        self.handlers.insert(name, route_handler);
+        self
+    }
+
+    /// Adds every procedure in `router` to this one. `router` can use any state that can be
+    /// derived from this router's state with [`FromRef`], like axum substates.
+    ///
+    /// Settings such as the transformer come from this router, the merged router's are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a merged procedure's path is already taken, or is a namespace of this router or
+    /// inside one of its procedures, like [`add`](Self::add).
+    pub fn merge<S2>(mut self, router: RpcRouter<S2>) -> Self
+    where
+        S2: FromRef<S1> + Clone + Send + Sync + 'static,
+    {
+        let router_middleware = router.middleware;
+        self.type_map.extend(router.type_map);
+        for (name, mut route_handler) in router.handlers {
+            self.check_path_free(&name, "merge");
+
+            route_handler
+                .middleware
+                .splice(0..0, router_middleware.iter().cloned());
+            self.handlers
+                .insert(name, route_handler.map_state(S2::from_ref));
+        }
+        self
+    }
+
+    /// Adds every procedure in `router` under `prefix`, so `get` nested under `"user"` is called
+    /// as `user.get`. Prefixes can themselves be dotted, e.g. `"billing.invoices"`. Like
+    /// [`merge`](Self::merge), `router` can use any state derived from this router's state.
+    ///
+    /// Settings such as the transformer come from the outer router, the nested router's are
+    /// ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a nested procedure's path is already taken, or is a namespace of this router or
+    /// inside one of its procedures, like [`add`](Self::add), e.g. nesting under
+    /// `"billing.invoices"` when `billing` is a procedure.
+    pub fn nest<S2>(mut self, prefix: &str, router: RpcRouter<S2>) -> Self
+    where
+        S2: FromRef<S1> + Clone + Send + Sync + 'static,
+    {
+        let router_middleware = router.middleware;
+        let mut type_map = router.type_map;
+        for (name, mut route_handler) in router.handlers {
+            let path = format!("{}.{}", prefix, name);
+            self.check_path_free(&path, "nest");
+
+            // Input types are named after the procedure, so `user.get` and `post.get` would both
+            // export a `GetInput`. Nested procedures get theirs inlined instead.
+            if let Some(input_type) = &mut route_handler.input_type
+                && let DataType::Reference(reference) = &input_type.inner
+                && let Some(named) = type_map.get(reference.sid())
+                && *named.name() == format!("{}Input", name.to_pascal_case())
+            {
+                let inlined = named.inner.clone();
+                type_map.remove(reference.sid());
+                *input_type = reference::custom(inlined);
+            }
+
+            route_handler.name = path.clone();
+            route_handler
+                .middleware
+                .splice(0..0, router_middleware.iter().cloned());
+            self.handlers
+                .insert(path, route_handler.map_state(S2::from_ref));
+        }
+
+        self.type_map.extend(type_map);
+        self
+    }
+
+    /// Panics if a procedure can't be added at `path`: a procedure or namespace already has the
+    /// path, or one of its dotted prefixes is a procedure. Both would export the same key twice,
+    /// e.g. `billing` as a procedure and as the namespace of `billing.invoices`.
+    fn check_path_free(&self, path: &str, action: &str) {
+        if self.handlers.contains_key(path) {
+            panic!(
+                "Cannot {} `{}`, a procedure with that path already exists",
+                action, path
+            );
+        }
+
+        let namespace = format!("{}.", path);
+        if self
+            .handlers
+            .keys()
+            .any(|existing| existing.starts_with(&namespace))
+        {
+            panic!(
+                "Cannot {} `{}`, a namespace with that path already exists",
+                action, path
+            );
+        }
+
+        if let Some(procedure) = path
+            .match_indices('.')
+            .map(|(end, _)| &path[..end])
+            .find(|prefix| self.handlers.contains_key(*prefix))
+        {
+            panic!(
+                "Cannot {} `{}`, `{}` is a procedure and can't also be a namespace",
+                action, path, procedure
+            );
+        }
+    }
+
+    /// Builds a context with `create_context`, like tRPC's `createContext`. Procedures receive
+    /// it through a [`Ctx<C>`](crate::context::Ctx) argument, and middleware can change it or
+    /// add other contexts with [`RpcCall::set_context`].
+    ///
+    /// The context is created once per request and shared by every call in a batch, or once
+    /// per WebSocket connection. It is created before any middleware runs, and an error fails
+    /// every call it would have been shared with.
+    ///
+    /// Like the transformer, only the context of the router that is served is used.
+    pub fn context<C, F, Fut>(mut self, create_context: F) -> Self
+    where
+        C: Clone + Send + Sync + 'static,
+        F: Fn(S1, Parts) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<C, RpcError>> + Send + 'static,
+    {
+        self.context = Some(Arc::new(move |state, parts| {
+            let ctx = create_context(state, parts);
+            Box::pin(async move {
+                let mut extensions = Extensions::new();
+                extensions.insert(Ctx(ctx.await?));
+                Ok(extensions)
+            })
+        }));
+        self
+    }
+
+    /// Sets how procedures marked with `#[rpc(.., auth)]` authenticate callers. Calls without
+    /// an identity are rejected with `UNAUTHORIZED` after the context is created and before any
+    /// middleware runs, and the identity is passed to the procedure as an
+    /// [`Identity`](crate::auth::Identity) argument.
+    ///
+    /// Like the transformer, only the authenticator of the router that is served is used.
+    pub fn authenticator<A: Authenticator<S1>>(mut self, authenticator: A) -> Self {
+        let authenticator = Arc::new(authenticator);
+        self.authenticator = Some(Arc::new(move |mut call: RpcCall<S1>, next: Next<S1>| {
+            let authenticator = authenticator.clone();
+            async move {
+                let identity = authenticator
+                    .authenticate(&call.parts, &call.state)
+                    .await?
+                    .ok_or_else(auth::unauthorized)?;
+                call.parts.extensions.insert(Identity(identity));
+                Ok(next.run(call).await)
+            }
+        }));
+        self
+    }
+
+    /// Sets how the permissions procedures declare with `#[rpc(.., requires = "..")]` are
+    /// checked. Calls missing a permission are rejected with `FORBIDDEN` right after they are
+    /// authenticated.
+    ///
+    /// Like the authenticator, only the resolver of the router that is served is used.
+    pub fn permission_resolver<R: PermissionResolver<S1>>(mut self, resolver: R) -> Self {
+        let resolver = Arc::new(resolver);
+        self.permission_resolver = Some(Arc::new(move |call: RpcCall<S1>, next: Next<S1>| {
+            let resolver = resolver.clone();
+            async move {
+                let identity = call
+                    .parts
+                    .extensions
+                    .get::<Identity<R::Identity>>()
+                    .ok_or_else(auth::unauthorized)?;
+                for permission in call.requires {
+                    if !resolver
+                        .has_permission(identity, permission, &call.state)
+                        .await?
+                    {
+                        return Err(auth::forbidden(&call.path, permission));
+                    }
+                }
+                Ok(next.run(call).await)
+            }
+        }));
+        self
+    }
+
+    /// The permissions each procedure requires, for procedures that require any.
+    pub fn required_permissions(&self) -> BTreeMap<&str, &'static [&'static str]> {
+        self.handlers
+            .values()
+            .filter(|route| !route.requires.is_empty())
+            .map(|route| (route.name.as_str(), route.requires))
+            .collect()
+    }
+
+    /// Runs `middleware` around every procedure in the router, including ones added later.
+    /// Middleware runs in the order it is added, outer routers' before that of routers nested or
+    /// merged into them.
+    pub fn middleware(mut self, middleware: impl Middleware<S1>) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sets the maximum number of calls accepted in a single batch request.
+    /// Larger batches are rejected with `BAD_REQUEST`. Defaults to 50.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Also accepts queries sent as POST with the input in the JSON body, for clients using
+    /// `methodOverride: 'POST'` to send inputs too long for a URL. Mutations are unaffected.
+    pub fn allow_method_override(mut self, allow_method_override: bool) -> Self {
+        self.allow_method_override = allow_method_override;
+        self
+    }
+
+    /// Also writes zod schemas for every type and procedure to `schemas.ts` next to the client,
+    /// for validating inputs and outputs at runtime. The generated schemas import `zod`.
+    pub fn zod_schemas(mut self, zod_schemas: bool) -> Self {
+        self.zod_schemas = zod_schemas;
+        self
+    }
+
+    /// Sets the transformer used to encode inputs and outputs, which must match the one the
+    /// client is created with. The generated client declares it in `initTRPC`.
+    pub fn transformer(mut self, transformer: Transformer) -> Self {
+        self.transformer = transformer;
+        self
+    }
+
+    /// Replaces the `data` object of error responses with the output of `formatter`, like tRPC's
+    /// `errorFormatter`. It receives the error, the procedure's path, its type and its input.
+    /// Typed error details and input issues are added to the output as `details` and `issues`,
+    /// and [`DefaultErrorData`](crate::errors::DefaultErrorData) can be flattened into it to
+    /// keep the default fields. The output type is exported to the generated client.
+    ///
+    /// Every error of a call to a known procedure is formatted, including ones from before it
+    /// runs such as using the wrong HTTP method or sending invalid input, and ones a
+    /// subscription's stream ends with. Errors from before a procedure is found, such as
+    /// unknown procedures or malformed batches, keep the default data. So does an error whose output
+    /// fails to serialize, e.g. because it holds a map with non-string keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `D` isn't a struct with named fields or a map, as the output must be an object
+    /// to hold `details` and `issues`.
+    pub fn error_formatter<D, F>(mut self, formatter: F) -> Self
+    where
+        D: Serialize + Type,
+        F: Fn(&RpcError, &str, RpcType, Option<&Value>) -> D + Send + Sync + 'static,
+    {
+        let data_type = D::reference(&mut self.type_map, &[]);
+        if !is_object(&data_type.inner, &self.type_map) {
+            panic!(
+                "The error formatter must return a struct with named fields or a map, as its output replaces the `data` object of errors"
+            );
+        }
+
+        self.error_data_type = Some(data_type);
+        self.error_formatter = Some(Arc::new(move |error, path, rpc_type, input| {
+            serde_json::to_value(formatter(error, path, rpc_type, input)).ok()
+        }));
         self
     }
 
     pub fn write_client(self, _path: &str) -> Result<Self, std::io::Error> {
         #[cfg(debug_assertions)]
         {
+            // superjson sends 64-bit integers as `bigint`, plain JSON can only send numbers
+            let bigint = match self.transformer {
+                Transformer::None => BigIntExportBehavior::Number,
+                Transformer::SuperJson => BigIntExportBehavior::BigInt,
+            };
             let export_config = Typescript::default()
-                .bigint(BigIntExportBehavior::Number)
+                .bigint(bigint)
                 .header("")
                 .framework_header("");
 
-            let mut router_entries = Vec::new();
-            let mut sorted_handlers: Vec<_> = self.handlers.iter().collect();
-            sorted_handlers.sort_by_key(|(name, _)| *name);
-            for (_name, route) in sorted_handlers {
+            let mut procedures = Vec::new();
+            for route in self.handlers.values() {
                 let type_method = match route.rpc_type {
                     RpcType::Query => "query",
                     RpcType::Mutation => "mutation",
+                    RpcType::Subscription => "subscription",
                 };
 
                 let call_error = "{ throw new Error('Router should not be used') }";
-                let route_name = &route.name;
                 let input_validator = if let Some(input_type) = &route.input_type {
                     let inlined = datatype(
                         &export_config,
@@ -110,7 +578,10 @@ impl<S1: Clone + Send + Sync + 'static> RpcRouter<S1> {
                     String::new()
                 };
 
-                let output_validator = if let Some(output_type) = &route.output_type {
+                let output_validator = if let (Some(output_type), false) = (
+                    &route.output_type,
+                    matches!(route.rpc_type, RpcType::Subscription),
+                ) {
                     let inlined = datatype(
                         &export_config,
                         &FunctionResultVariant::Value(output_type.inner.clone()),
@@ -131,14 +602,115 @@ impl<S1: Clone + Send + Sync + 'static> RpcRouter<S1> {
                     .unwrap(),
                     None => "void".to_string(),
                 };
+                let output_type = match route.rpc_type {
+                    RpcType::Subscription if route.tracked => {
+                        format!("AsyncGenerator<TrackedEnvelope<{output_type}>>")
+                    }
+                    RpcType::Subscription => format!("AsyncGenerator<{output_type}>"),
+                    _ => output_type,
+                };
 
+                let procedure = if route.auth {
+                    "protectedProcedure"
+                } else {
+                    "publicProcedure"
+                };
                 let route_func = format!(
-                    "{route_name}: publicProcedure{input_validator}{output_validator}.{type_method}((opts): {output_type} => {call_error})",
+                    "{procedure}{input_validator}{output_validator}.{type_method}((opts): {output_type} => {call_error})",
                 );
 
-                router_entries.push(route_func);
+                let doc = match route.requires {
+                    [] => String::new(),
+                    requires => format!(
+                        "/** Requires {} */\n",
+                        requires
+                            .iter()
+                            .map(|permission| format!("`{permission}`"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                };
+
+                procedures.push((route.name.split('.').collect::<Vec<_>>(), doc, route_func));
             }
 
+            procedures.sort();
+            let router_entries = client_router_entries(&procedures);
+
+            let server_imports = if self.handlers.values().any(|route| route.tracked) {
+                "initTRPC, type TrackedEnvelope"
+            } else {
+                "initTRPC"
+            };
+
+            let mut init_options = Vec::new();
+            let transformer_import = match self.transformer {
+                Transformer::None => "",
+                Transformer::SuperJson => {
+                    init_options.push("transformer: superjson");
+                    "\nimport superjson from 'superjson';"
+                }
+            };
+
+            // tRPC's error shape is shared by every procedure, so `details` is typed as any of
+            // the procedures' error details
+            let mut error_details: Vec<_> = self
+                .handlers
+                .values()
+                .filter_map(|route| route.error_type.as_ref())
+                .map(|error_type| {
+                    datatype(
+                        &export_config,
+                        &FunctionResultVariant::Value(error_type.inner.clone()),
+                        &self.type_map,
+                    )
+                    .unwrap()
+                })
+                .collect();
+            error_details.sort();
+            error_details.dedup();
+            let error_details = if error_details.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\n\nexport type ErrorDetails = {};",
+                    error_details.join(" | ")
+                )
+            };
+
+            // The mock router never formats errors, the cast only carries the type of `data`
+            let error_data = match &self.error_data_type {
+                Some(error_data_type) => datatype(
+                    &export_config,
+                    &FunctionResultVariant::Value(error_data_type.inner.clone()),
+                    &self.type_map,
+                )
+                .unwrap(),
+                None => "typeof shape.data".to_string(),
+            };
+            let details = if error_details.is_empty() {
+                ""
+            } else {
+                "; details?: ErrorDetails"
+            };
+            let error_formatter = format!(
+                "errorFormatter: ({{ shape }}) => ({{ ...shape, data: shape.data as unknown as {error_data} & {{ issues?: ValidationIssue[]{details} }} }})"
+            );
+            init_options.push(&error_formatter);
+
+            let init_options = if init_options.is_empty() {
+                String::new()
+            } else {
+                format!("{{ {} }}", init_options.join(", "))
+            };
+
+            // Only marks which procedures need authentication, the mock router never runs them
+            let protected_procedure = if self.handlers.values().any(|route| route.auth) {
+                "\nconst protectedProcedure = t.procedure;"
+            } else {
+                ""
+            };
+
             let type_defs = export_config.export(&self.type_map).unwrap();
             let type_defs = type_defs.trim();
             let router_def = formatdoc!(
@@ -146,12 +718,12 @@ impl<S1: Clone + Send + Sync + 'static> RpcRouter<S1> {
                 /* eslint-disable */
                 /* tslint:disable */
                 /* Generated by Specta for Juno. DO NOT EDIT */
-                import {{ initTRPC }} from '@trpc/server';
+                import {{ {server_imports} }} from '@trpc/server';{transformer_import}
 
-                {type_defs}
+                {type_defs}{error_details}
 
-                const t = initTRPC.create();
-                const publicProcedure = t.procedure;
+                const t = initTRPC.create({init_options});
+                const publicProcedure = t.procedure;{protected_procedure}
                 const appRouter = t.router({{
                     {}
                 }});
@@ -162,109 +734,594 @@ impl<S1: Clone + Send + Sync + 'static> RpcRouter<S1> {
             );
 
             std::fs::write(_path, router_def)?;
+
+            if self.zod_schemas {
+                let schemas_path = std::path::Path::new(_path).with_file_name("schemas.ts");
+                std::fs::write(schemas_path, self.zod_schemas_def())?;
+            }
         }
 
         Ok(self)
     }
 
+    /// The contents of `schemas.ts`: a schema for every type, then the input and output schemas
+    /// of each procedure.
+    #[cfg(debug_assertions)]
+    fn zod_schemas_def(&self) -> String {
+        let exporter = ZodExporter::new(
+            &self.type_map,
+            matches!(self.transformer, Transformer::SuperJson),
+        );
+
+        // Input structs get their fields' `#[validate(..)]` rules
+        let mut rules = HashMap::new();
+        for route in self.handlers.values() {
+            if let Some(input_type) = &route.input_type
+                && route.rules.iter().any(|(field, _)| !field.is_empty())
+                && let DataType::Reference(reference) = &input_type.inner
+                && let Some(named) = self.type_map.get(reference.sid())
+            {
+                rules.insert(named.name().as_ref(), route.rules);
+            }
+        }
+        let type_schemas = exporter.export(&rules);
+
+        let mut procedures: Vec<_> = self.handlers.values().collect();
+        procedures.sort_by(|a, b| a.name.cmp(&b.name));
+        let procedures: Vec<_> = procedures
+            .into_iter()
+            .map(|route| {
+                let input = match &route.input_type {
+                    Some(input_type) => exporter.input(&input_type.inner, route.rules),
+                    None => "z.undefined()".to_string(),
+                };
+                let output = match &route.output_type {
+                    // Tracked events reach the client as `{ id, data }`, see `TrackedEnvelope`
+                    Some(output_type) if route.tracked => format!(
+                        "z.object({{ id: z.string(), data: {} }})",
+                        exporter.datatype(&output_type.inner)
+                    ),
+                    Some(output_type) => exporter.datatype(&output_type.inner),
+                    None => "z.null()".to_string(),
+                };
+                format!(
+                    "    {}: {{ input: {input}, output: {output} }}",
+                    zod::key(&route.name)
+                )
+            })
+            .collect();
+
+        formatdoc!(
+            "
+            /* eslint-disable */
+            /* tslint:disable */
+            /* Generated by Specta for Juno. DO NOT EDIT */
+            import {{ z }} from 'zod';
+
+            {type_schemas}
+
+            export const procedures = {{
+            {}
+            }};
+            ",
+            procedures.join(",\n")
+        )
+    }
+
+    /// Serves every procedure over HTTP at `/{procedure}`. With the `ws` feature, the root
+    /// path also accepts WebSocket connections from tRPC's `wsLink`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a procedure requires authentication or permissions and no authenticator or
+    /// permission resolver was set.
     pub fn to_router(self) -> Router<S1> {
-        let handlers = self.handlers.clone();
-        Router::<S1>::new().route(
+        if self.permission_resolver.is_none()
+            && let Some(route) = self
+                .handlers
+                .values()
+                .find(|route| !route.requires.is_empty())
+        {
+            panic!(
+                "`{}` requires permissions, but the router has no permission resolver",
+                route.name
+            );
+        }
+
+        if self.authenticator.is_none()
+            && let Some(route) = self.handlers.values().find(|route| route.auth)
+        {
+            panic!(
+                "`{}` requires authentication, but the router has no authenticator",
+                route.name
+            );
+        }
+
+        let rpc = Arc::new(self);
+        let http_rpc = rpc.clone();
+        let router = Router::<S1>::new().route(
             "/{*rpc_method_name}",
-            any(move |state: State<S1>, req: Request<Body>| async move {
-                let handlers = handlers.clone();
-                let name = req.uri().path().trim_start_matches("/").to_string();
-
-                let Some(handler) = handlers.get(&name) else {
-                    return RpcError::new(
-                        RpcStatus::NotFound,
-                        format!("Handler `{}` not found", name),
-                    )
-                    .into_rpc_response()
-                    .into_response();
-                };
+            any(move |state: State<S1>, req: Request<Body>| {
+                let rpc = http_rpc.clone();
+                async move { rpc.handle_request(state.0, req).await }
+            }),
+        );
 
-                let method = req.method().clone();
-                let (mut parts, body) = req.into_parts();
-                let query = match handler.rpc_type {
-                    RpcType::Query => {
-                        if method != Method::GET && method != Method::HEAD {
-                            return RpcError::new(
-                                RpcStatus::MethodNotSupported,
-                                format!("Method `{}` not supported for RPC", method),
-                            )
-                            .into_rpc_response()
-                            .into_response();
-                        }
-
-                        match Query::<RpcQuery>::from_request_parts(&mut parts, &state).await {
-                            Ok(query) => query.0,
-                            Err(err) => {
-                                return RpcError::new(
-                                    RpcStatus::BadRequest,
-                                    format!("Invalid input for `{}`: {}", name, err),
-                                )
-                                .into_rpc_response()
-                                .into_response();
-                            }
-                        }
-                    }
-                    RpcType::Mutation => {
-                        if method != Method::POST {
-                            return RpcError::new(
-                                RpcStatus::MethodNotSupported,
-                                format!("Method `{}` not supported for RPC", method),
-                            )
-                            .into_rpc_response()
-                            .into_response();
-                        }
-
-                        let bytes = match axum::body::to_bytes(body, MAX_BODY_SIZE).await {
-                            Ok(bytes) => bytes,
-                            Err(err) => {
-                                return RpcError::new(
-                                    RpcStatus::BadRequest,
-                                    format!("Failed to read request body: {}", err),
-                                )
-                                .into_rpc_response()
-                                .into_response();
-                            }
-                        };
-
-                        let input = if bytes.is_empty() {
-                            None
-                        } else {
-                            match serde_json::from_slice::<Value>(&bytes) {
-                                Ok(value) => Some(value),
-                                Err(err) => {
-                                    return RpcError::new(
-                                        RpcStatus::BadRequest,
-                                        format!("Invalid JSON in request body: {}", err),
-                                    )
-                                    .into_rpc_response()
-                                    .into_response();
-                                }
-                            }
-                        };
-
-                        RpcQuery { batch: None, input }
-                    }
-                };
+        #[cfg(feature = "ws")]
+        let router = router.route(
+            "/",
+            any(move |state: State<S1>, req: Request<Body>| {
+                crate::ws::upgrade(rpc.clone(), state.0, req)
+            }),
+        );
 
-                if query.batch.unwrap_or(false) {
-                    return RpcError::new(
-                        RpcStatus::NotImplemented,
-                        "Batch requests are not supported".to_string(),
-                    )
-                    .into_rpc_response()
-                    .into_response();
+        router
+    }
+
+    pub(crate) fn procedure(&self, path: &str) -> Result<&RpcMethod<S1>, RpcError> {
+        self.handlers.get(path).ok_or_else(|| {
+            RpcError::new(RpcStatus::NotFound, format!("Handler `{}` not found", path))
+        })
+    }
+
+    /// Starts a call to `procedure`. Every transport runs its procedures through here.
+    pub(crate) fn call(
+        &self,
+        procedure: &RpcMethod<S1>,
+        input: Option<Value>,
+        state: S1,
+        parts: Parts,
+    ) -> RpcFuture {
+        let errors = self
+            .error_formatter
+            .is_some()
+            .then(|| self.call_errors(procedure, input.as_ref()));
+        let call = RpcCall {
+            path: procedure.name.clone(),
+            rpc_type: procedure.rpc_type,
+            requires: procedure.requires,
+            input,
+            state,
+            parts,
+        };
+        let authenticator = self.authenticator.iter().filter(|_| procedure.auth);
+        let permission_resolver = self
+            .permission_resolver
+            .iter()
+            .filter(|_| !procedure.requires.is_empty());
+        let middleware = authenticator
+            .chain(permission_resolver)
+            .chain(&self.middleware)
+            .chain(&procedure.middleware)
+            .cloned()
+            .collect();
+        let response = crate::middleware::run_chain(middleware, procedure.handler.clone(), call);
+        let Some(errors) = errors else {
+            return response;
+        };
+
+        Box::pin(async move {
+            let mut response = response.await;
+            errors.format(&mut response);
+            response
+        })
+    }
+
+    /// Formats the errors of a call to `procedure` with `input`, including those a subscription
+    /// yields after the call returned.
+    pub(crate) fn call_errors(
+        &self,
+        procedure: &RpcMethod<S1>,
+        input: Option<&Value>,
+    ) -> CallErrors {
+        CallErrors {
+            // The input is only needed by the formatter
+            input: self.error_formatter.as_ref().and(input.cloned()),
+            formatter: self.error_formatter.clone(),
+            path: procedure.name.clone(),
+            rpc_type: procedure.rpc_type,
+        }
+    }
+
+    /// The response to a call to `procedure` that failed before it could run, formatted like
+    /// the procedure's own errors.
+    pub(crate) fn procedure_error(
+        &self,
+        err: RpcError,
+        procedure: &RpcMethod<S1>,
+        input: Option<&Value>,
+    ) -> RpcResponse {
+        let mut response = err.into_rpc_response();
+        if let Some(formatter) = &self.error_formatter {
+            format_error(
+                formatter,
+                &mut response,
+                &procedure.name,
+                procedure.rpc_type,
+                input,
+            );
+        }
+        response
+    }
+
+    /// Creates the context shared by every call made with `parts`, see [`RpcRouter::context`].
+    pub(crate) async fn create_context(
+        &self,
+        state: &S1,
+        parts: &mut Parts,
+    ) -> Result<(), RpcError> {
+        if let Some(context) = &self.context {
+            let extensions = context(state.clone(), parts.clone()).await?;
+            parts.extensions.extend(extensions);
+        }
+        Ok(())
+    }
+
+    /// Sends a JSON response, encoded with the router's transformer.
+    fn respond(&self, response: RpcResponse) -> Response {
+        let status = response.status();
+        match response.encoded_value(self.transformer) {
+            Some(body) => (status, Json(body)).into_response(),
+            None => response.into_response(),
+        }
+    }
+
+    fn respond_error(&self, err: RpcError) -> Response {
+        self.respond(err.into_rpc_response())
+    }
+
+    /// Handles a request, adding anything procedures set on its [`ResponseMeta`] to the response.
+    async fn handle_request(&self, state: S1, mut req: Request<Body>) -> Response {
+        let meta = ResponseMeta::default();
+        req.extensions_mut().insert(meta.clone());
+        let response = self.dispatch(state, req).await;
+        meta.apply(response)
+    }
+
+    async fn dispatch(&self, state: S1, req: Request<Body>) -> Response {
+        let path = req.uri().path().trim_start_matches("/").to_string();
+        let query_size = req.uri().query().map_or(0, str::len);
+        if query_size > MAX_QUERY_SIZE {
+            return self.respond_error(RpcError::new(
+                RpcStatus::PayloadTooLarge,
+                format!(
+                    "Query string of {} bytes exceeds the maximum size of {} bytes",
+                    query_size, MAX_QUERY_SIZE
+                ),
+            ));
+        }
+
+        let query = match Query::<RpcQuery>::try_from_uri(req.uri()) {
+            Ok(query) => query.0,
+            Err(err) => {
+                return self.respond_error(RpcError::new(
+                    RpcStatus::BadRequest,
+                    format!("Invalid input for `{}`: {}", path, err),
+                ));
+            }
+        };
+
+        if query.batch.unwrap_or(false) {
+            return self.handle_batch(state, req, &path, query).await;
+        }
+
+        let handler = match self.procedure(&path) {
+            Ok(handler) => handler,
+            Err(err) => return self.respond_error(err),
+        };
+
+        let method = req.method().clone();
+        if let Err(err) = self.check_method(handler.rpc_type, &method) {
+            return self.respond(self.procedure_error(err, handler, None));
+        }
+
+        let (mut parts, body) = req.into_parts();
+        let max_body_size = handler.max_body_size.unwrap_or(MAX_BODY_SIZE);
+        let input = if method != Method::POST {
+            Ok(query.input)
+        } else {
+            match handler.input_format {
+                InputFormat::Json => read_json_body(body, max_body_size).await,
+                InputFormat::FormData => FormData::from_body(&parts.headers, body, max_body_size)
+                    .map(|form_data| {
+                        parts.extensions.insert(PendingBody::new(form_data));
+                        None
+                    }),
+                InputFormat::OctetStream => {
+                    ByteStream::from_body(&parts.headers, body, max_body_size).map(|bytes| {
+                        parts.extensions.insert(PendingBody::new(bytes));
+                        None
+                    })
                 }
+            }
+        };
+        let input = match input {
+            Ok(input) => input,
+            Err(err) => return self.respond(self.procedure_error(err, handler, None)),
+        };
+
+        let mut input = match self.transformer.deserialize(input) {
+            Ok(input) => input,
+            Err(err) => return self.respond(self.procedure_error(err, handler, None)),
+        };
+
+        if handler.rpc_type == RpcType::Subscription {
+            let last_event_id = LastEventId::take(&parts.headers, &mut input);
+            parts.extensions.insert(last_event_id);
+        }
+
+        if let Err(err) = self.create_context(&state, &mut parts).await {
+            return self.respond(self.procedure_error(err, handler, input.as_ref()));
+        }
+
+        if handler.rpc_type != RpcType::Subscription {
+            return self.respond(self.call(handler, input, state, parts).await);
+        }
 
-                let result = (handler.handler)(query.input, state.0, parts).await;
-                result.into_response()
+        let errors = self.call_errors(handler, input.as_ref());
+        match self.call(handler, input, state, parts).await.into_events() {
+            Ok(events) => sse::event_stream_response(events, self.transformer, move |err| {
+                errors.response(err)
             }),
+            Err(result) => sse::error_response(result, self.transformer),
+        }
+    }
+
+    /// Handles a tRPC `httpBatchLink` request, where `path` is a comma-separated list of
+    /// procedures and the input is an object keyed by each call's index in the batch.
+    /// Requests from `httpBatchStreamLink` get their results streamed back as they finish.
+    async fn handle_batch(
+        &self,
+        state: S1,
+        req: Request<Body>,
+        path: &str,
+        query: RpcQuery,
+    ) -> Response {
+        let names: Vec<&str> = path.split(',').collect();
+        if names.len() > self.max_batch_size {
+            return self.respond_error(RpcError::new(
+                RpcStatus::BadRequest,
+                format!(
+                    "Batch of {} calls exceeds the maximum batch size of {}",
+                    names.len(),
+                    self.max_batch_size
+                ),
+            ));
+        }
+
+        let method = req.method().clone();
+        let stream = jsonl::accepts_jsonl(req.headers());
+        let (mut parts, body) = req.into_parts();
+        let inputs = if method == Method::POST {
+            match read_json_body(body, MAX_BODY_SIZE).await {
+                Ok(input) => input,
+                Err(err) => return self.respond_error(err),
+            }
+        } else {
+            query.input
+        };
+
+        let mut inputs = match inputs {
+            None | Some(Value::Null) => serde_json::Map::new(),
+            Some(Value::Object(inputs)) => inputs,
+            Some(_) => {
+                return self.respond_error(RpcError::new(
+                    RpcStatus::BadRequest,
+                    "Batch input must be an object keyed by call index".to_string(),
+                ));
+            }
+        };
+
+        let context = self.create_context(&state, &mut parts).await;
+        let calls: Vec<_> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let input = self
+                    .transformer
+                    .deserialize(inputs.remove(&index.to_string()));
+                let call = self
+                    .procedure(name)
+                    .map_err(IntoRpcResponse::into_rpc_response)
+                    .and_then(|handler| {
+                        self.check_batched(handler, &method)
+                            .and(context.clone())
+                            .and(input)
+                            .map(|input| self.call(handler, input, state.clone(), parts.clone()))
+                            .map_err(|err| self.procedure_error(err, handler, None))
+                    });
+
+                async move {
+                    match call {
+                        Ok(future) => future.await,
+                        Err(response) => response,
+                    }
+                }
+            })
+            .collect();
+
+        if stream {
+            return jsonl::batch_stream_response(calls, self.transformer);
+        }
+
+        let results = join_all(calls).await;
+        let mut statuses = results.iter().map(|result| result.status());
+        let first_status = statuses.next().unwrap_or(StatusCode::OK);
+        let status = if statuses.all(|status| status == first_status) {
+            first_status
+        } else {
+            StatusCode::MULTI_STATUS
+        };
+
+        let body: Vec<Value> = results
+            .into_iter()
+            .map(|result| result.encoded_value(self.transformer).unwrap_or_default())
+            .collect();
+
+        (status, Json(body)).into_response()
+    }
+
+    fn check_batched(&self, procedure: &RpcMethod<S1>, method: &Method) -> Result<(), RpcError> {
+        if procedure.rpc_type == RpcType::Subscription {
+            return Err(RpcError::new(
+                RpcStatus::BadRequest,
+                format!("Subscription `{}` cannot be batched", procedure.name),
+            ));
+        }
+        if !procedure.uses_json() {
+            return Err(RpcError::new(
+                RpcStatus::BadRequest,
+                format!(
+                    "`{}` does not use JSON and cannot be batched",
+                    procedure.name
+                ),
+            ));
+        }
+
+        self.check_method(procedure.rpc_type, method)
+    }
+
+    fn check_method(&self, rpc_type: RpcType, method: &Method) -> Result<(), RpcError> {
+        let allowed = match rpc_type {
+            RpcType::Query if self.allow_method_override && method == Method::POST => true,
+            RpcType::Query | RpcType::Subscription => {
+                method == Method::GET || method == Method::HEAD
+            }
+            RpcType::Mutation => method == Method::POST,
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(RpcError::new(
+                RpcStatus::MethodNotSupported,
+                format!("Method `{}` not supported for RPC", method),
+            ))
+        }
+    }
+}
+
+/// Replaces the data of an error response with the output of `formatter`, adding the error's
+/// details and issues to it.
+/// The errors of one call to a procedure, see [`RpcRouter::call_errors`].
+#[derive(Clone)]
+pub(crate) struct CallErrors {
+    formatter: Option<ErrorFormatter>,
+    path: String,
+    rpc_type: RpcType,
+    input: Option<Value>,
+}
+
+impl CallErrors {
+    pub(crate) fn response(&self, err: RpcError) -> RpcResponse {
+        let mut response = err.into_rpc_response();
+        self.format(&mut response);
+        response
+    }
+
+    fn format(&self, response: &mut RpcResponse) {
+        if let Some(formatter) = &self.formatter {
+            format_error(
+                formatter,
+                response,
+                &self.path,
+                self.rpc_type,
+                self.input.as_ref(),
+            );
+        }
+    }
+}
+
+fn format_error(
+    formatter: &ErrorFormatter,
+    response: &mut RpcResponse,
+    path: &str,
+    rpc_type: RpcType,
+    input: Option<&Value>,
+) {
+    let data = response.error().and_then(|error| {
+        let mut data = formatter(error, path, rpc_type, input)?;
+        if let Value::Object(data) = &mut data {
+            if let Some(details) = &error.details {
+                data.insert("details".to_string(), details.clone());
+            }
+            if !error.issues.is_empty() {
+                data.insert("issues".to_string(), serde_json::json!(error.issues));
+            }
+        }
+        Some(data)
+    });
+    if let Some(data) = data {
+        response.set_error_data(data);
+    }
+}
+
+/// Whether values of `ty` are serialized as JSON objects.
+fn is_object(ty: &DataType, types: &TypeCollection) -> bool {
+    match ty {
+        DataType::Reference(reference) => types
+            .get(reference.sid())
+            .is_some_and(|named| is_object(&named.inner, types)),
+        DataType::Struct(ty) => matches!(ty.fields(), StructFields::Named(_)),
+        DataType::Map(_) => true,
+        _ => false,
+    }
+}
+
+/// Renders the entries of a `t.router({ ... })` from procedures sorted by path, with
+/// procedures that share a leading segment grouped into a nested `t.router`.
+#[cfg(debug_assertions)]
+fn client_router_entries(procedures: &[(Vec<&str>, String, String)]) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut index = 0;
+    while index < procedures.len() {
+        let (path, doc, route_func) = &procedures[index];
+        if let [name] = path.as_slice() {
+            entries.push(format!("{doc}{name}: {route_func}"));
+            index += 1;
+            continue;
+        }
+
+        let namespace = path[0];
+        let children: Vec<_> = procedures[index..]
+            .iter()
+            .take_while(|(path, _, _)| path.len() > 1 && path[0] == namespace)
+            .map(|(path, doc, route_func)| (path[1..].to_vec(), doc.clone(), route_func.clone()))
+            .collect();
+        index += children.len();
+
+        let children = client_router_entries(&children).join(",\n");
+        let children = children
+            .lines()
+            .map(|line| format!("    {line}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        entries.push(format!("{namespace}: t.router({{\n{children}\n}})"));
+    }
+
+    entries
+}
+
+async fn read_json_body(body: Body, max_size: usize) -> Result<Option<Value>, RpcError> {
+    let bytes = axum::body::to_bytes(body, max_size).await.map_err(|err| {
+        RpcError::new(
+            RpcStatus::BadRequest,
+            format!("Failed to read request body: {}", err),
         )
+    })?;
+
+    if bytes.is_empty() {
+        return Ok(None);
     }
+
+    serde_json::from_slice::<Value>(&bytes)
+        .map(Some)
+        .map_err(|err| {
+            RpcError::new(
+                RpcStatus::BadRequest,
+                format!("Invalid JSON in request body: {}", err),
+            )
+        })
 }
 
 #[derive(Deserialize)]
@@ -305,3 +1362,138 @@ where
         _ => Err(de::Error::custom("Expected a bool-ish string")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A query at `path` that is never called, as `#[rpc]` would define it.
+    fn procedure(path: &str) -> impl FnOnce(&mut TypeCollection) -> RpcMethod<()> {
+        procedure_with(path, RpcType::Query, || {
+            RpcResponse::new(StatusCode::OK, Value::Null)
+        })
+    }
+
+    fn procedure_with(
+        path: &str,
+        rpc_type: RpcType,
+        respond: fn() -> RpcResponse,
+    ) -> impl FnOnce(&mut TypeCollection) -> RpcMethod<()> {
+        let name = path.to_string();
+        move |_types| RpcMethod {
+            name,
+            rpc_type,
+            input_type: None,
+            output_type: None,
+            error_type: None,
+            tracked: false,
+            input_format: InputFormat::Json,
+            output_format: OutputFormat::Json,
+            max_body_size: None,
+            auth: false,
+            requires: &[],
+            rules: &[],
+            middleware: Vec::new(),
+            handler: Arc::new(move |_input, _state, _parts| Box::pin(async move { respond() })),
+        }
+    }
+
+    fn paths(router: &RpcRouter<()>) -> Vec<&str> {
+        let mut paths: Vec<_> = router.handlers.keys().map(String::as_str).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn procedures_keep_their_paths() {
+        let invoices = RpcRouter::new().add(procedure("list"));
+        let router = RpcRouter::new()
+            .add(procedure("user"))
+            .nest("billing.invoices", invoices)
+            .merge(RpcRouter::new().add(procedure("users")));
+        assert_eq!(paths(&router), ["billing.invoices.list", "user", "users"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "`billing` is a procedure and can't also be a namespace")]
+    fn nesting_under_a_procedure_panics() {
+        let invoices = RpcRouter::new().add(procedure("list"));
+        let _ = RpcRouter::new()
+            .add(procedure("billing"))
+            .nest("billing.invoices", invoices);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot nest `user.get`, a procedure with that path already exists")]
+    fn nesting_over_a_procedure_panics() {
+        let user = RpcRouter::new().add(procedure("get"));
+        let _ = RpcRouter::new()
+            .add(procedure("user.get"))
+            .nest("user", user);
+    }
+
+    #[test]
+    #[should_panic(expected = "`user` is a procedure and can't also be a namespace")]
+    fn merging_a_namespace_under_a_procedure_panics() {
+        let _ = RpcRouter::new()
+            .add(procedure("user"))
+            .merge(RpcRouter::new().add(procedure("user.get")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot merge `user`, a namespace with that path already exists")]
+    fn merging_a_procedure_over_a_namespace_panics() {
+        let user = RpcRouter::new().add(procedure("get"));
+        let _ = RpcRouter::new()
+            .nest("user", user)
+            .merge(RpcRouter::new().add(procedure("user")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add `user`, a namespace with that path already exists")]
+    fn adding_a_procedure_over_a_namespace_panics() {
+        let user = RpcRouter::new().add(procedure("get"));
+        let _ = RpcRouter::new().nest("user", user).add(procedure("user"));
+    }
+
+    #[tokio::test]
+    async fn subscription_errors_are_formatted() {
+        #[derive(Serialize, Type)]
+        struct ErrorData {
+            path: String,
+            input: Option<Value>,
+        }
+
+        let rpc = RpcRouter::new()
+            .add(procedure_with("watch", RpcType::Subscription, || {
+                RpcResponse::try_subscription(futures_util::stream::iter([
+                    Ok(1),
+                    Err(RpcError::new(RpcStatus::Conflict, "Gone".to_string())),
+                ]))
+            }))
+            .error_formatter(|_error, path, _rpc_type, input| ErrorData {
+                path: path.to_string(),
+                input: input.cloned(),
+            });
+
+        let request = Request::get("/watch?input=%7B%22id%22%3A5%7D")
+            .body(Body::empty())
+            .unwrap();
+        let response = rpc.dispatch((), request).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let error = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .skip_while(|line| *line != "event: serialized-error")
+            .find_map(|line| line.strip_prefix("data: ").map(str::to_string))
+            .expect("the stream should end with an error");
+        let error: Value = serde_json::from_str(&error).unwrap();
+        assert_eq!(error["message"], "Gone");
+        assert_eq!(
+            error["data"],
+            serde_json::json!({ "path": "watch", "input": { "id": 5 } })
+        );
+    }
+}
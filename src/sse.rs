@@ -0,0 +1,100 @@
+use crate::{
+    errors::RpcError,
+    response::{RpcEventStream, RpcResponse},
+    transformer::{Transformer, TypeAnnotations},
+};
+use axum::{
+    http::{HeaderValue, header::HeaderName},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::{StreamExt, stream};
+use serde_json::Value;
+use std::convert::Infallible;
+
+// Event names used by tRPC's `httpSubscriptionLink`.
+const CONNECTED_EVENT: &str = "connected";
+const SERIALIZED_ERROR_EVENT: &str = "serialized-error";
+const RETURN_EVENT: &str = "return";
+
+/// Serves a subscription as `text/event-stream` in the format `httpSubscriptionLink` expects.
+///
+/// The stream opens with a `connected` event and ends with a `return` event once the
+/// subscription completes. An error is turned into a response by `on_error`, sent as a
+/// `serialized-error` event, and closes the stream.
+pub(crate) fn event_stream_response<F>(
+    events: RpcEventStream,
+    transformer: Transformer,
+    on_error: F,
+) -> Response
+where
+    F: Fn(RpcError) -> RpcResponse + Send + 'static,
+{
+    let events = stream::unfold(Some((events, on_error)), move |state| async move {
+        let (mut events, on_error) = state?;
+        let event = match events.next().await {
+            Some(Ok(event)) => {
+                let data = transformer.serialize(event.data, &event.annotations);
+                let sse_event = Event::default().data(data.to_string());
+                match event.id {
+                    Some(id) => sse_event.id(id),
+                    None => sse_event,
+                }
+            }
+            Some(Err(err)) => return Some((serialized_error(on_error(err), transformer), None)),
+            None => return Some((Event::default().event(RETURN_EVENT).data(""), None)),
+        };
+
+        Some((event, Some((events, on_error))))
+    });
+
+    let connected = Event::default().event(CONNECTED_EVENT).data("{}");
+    let stream = stream::once(async move { connected })
+        .chain(events)
+        .map(Ok::<_, Infallible>);
+
+    sse_response(stream)
+}
+
+/// Serves a subscription that failed before it produced a stream, such as one called with
+/// invalid input, as a single `serialized-error` event.
+pub(crate) fn error_response(response: RpcResponse, transformer: Transformer) -> Response {
+    let stream = stream::iter([
+        Event::default().event(CONNECTED_EVENT).data("{}"),
+        serialized_error(response, transformer),
+    ])
+    .map(Ok::<_, Infallible>);
+
+    sse_response(stream)
+}
+
+fn serialized_error(response: RpcResponse, transformer: Transformer) -> Event {
+    let shape = response
+        .value()
+        .and_then(|value| value.get("error"))
+        .cloned()
+        .unwrap_or(Value::Null);
+    let shape = transformer.serialize(shape, &TypeAnnotations::default());
+
+    Event::default()
+        .event(SERIALIZED_ERROR_EVENT)
+        .data(shape.to_string())
+}
+
+fn sse_response<St>(stream: St) -> Response
+where
+    St: futures_util::Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let mut response = Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+
+    // Stops reverse proxies such as nginx from buffering events.
+    response.headers_mut().insert(
+        HeaderName::from_static("x-accel-buffering"),
+        HeaderValue::from_static("no"),
+    );
+    response
+}
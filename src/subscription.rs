@@ -0,0 +1,95 @@
+use crate::{
+    errors::{RpcError, RpcStatus},
+    response::{IntoRpcEvent, RpcEvent},
+    transformer,
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, request::Parts},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::convert::Infallible;
+
+/// The input key `httpSubscriptionLink` uses to send the last event id when it reconnects.
+const LAST_EVENT_ID_INPUT: &str = "lastEventId";
+
+/// A subscription item tagged with an event id, equivalent to tRPC's `tracked()`.
+///
+/// The id is sent to the client with the item, and the client sends back the last id it saw
+/// when it reconnects so the subscription can resume from there. See [`LastEventId`].
+pub struct Tracked<T> {
+    pub id: String,
+    pub data: T,
+}
+
+/// Tags `data` with an event id, see [`Tracked`].
+pub fn tracked<T>(id: impl Into<String>, data: T) -> Tracked<T> {
+    Tracked {
+        id: id.into(),
+        data,
+    }
+}
+
+impl<T: Serialize + specta::Type> IntoRpcEvent for Tracked<T> {
+    type Output = T;
+    const TRACKED: bool = true;
+
+    fn into_rpc_event(self) -> Result<RpcEvent, RpcError> {
+        match transformer::to_value(&self.data) {
+            Ok((data, annotations)) => Ok(RpcEvent {
+                id: Some(self.id),
+                data,
+                annotations,
+            }),
+            Err(_) => Err(RpcError::new(
+                RpcStatus::InternalServerError,
+                "Failed to serialize subscription event".to_string(),
+            )),
+        }
+    }
+}
+
+/// The id of the last [`Tracked`] event the client received, if it is reconnecting.
+///
+/// Taken from the `Last-Event-Id` header or the `lastEventId` input sent by
+/// `httpSubscriptionLink`. Add it as an `#[extract]` argument to a subscription to resume from
+/// that event.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LastEventId(pub Option<String>);
+
+impl LastEventId {
+    /// Pulls the last event id out of a subscription request. The `lastEventId` key is removed
+    /// from `input` so it does not reach the procedure's own input.
+    pub(crate) fn take(headers: &HeaderMap, input: &mut Option<Value>) -> Self {
+        let from_input = match input {
+            Some(Value::Object(object)) => object.remove(LAST_EVENT_ID_INPUT),
+            _ => None,
+        };
+
+        let from_header = headers
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        let from_input = from_input.and_then(|value| match value {
+            Value::String(id) => Some(id),
+            Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        });
+
+        LastEventId(from_header.or(from_input))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for LastEventId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<LastEventId>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,861 @@
+use crate::errors::{RpcError, RpcStatus};
+use serde::{Serialize, ser};
+use serde_json::{Map, Value, json};
+
+type SerializeResult = Result<Value, serde_json::Error>;
+
+/// How payloads are encoded on the wire, matching the `transformer` the tRPC client uses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transformer {
+    /// Plain JSON, for clients without a transformer.
+    #[default]
+    None,
+    /// [superjson](https://github.com/flightcontrolhq/superjson), which wraps payloads as
+    /// `{ json, meta }` so the client can restore types JSON cannot represent.
+    ///
+    /// 64-bit integers are sent as `bigint`, and values serialized like chrono's `DateTime`
+    /// (RFC 3339 strings written through `Serializer::collect_str`) are sent as `Date`.
+    /// Strings serialized as strings, like `String` fields, are never sent as `Date`, but other
+    /// types written through `collect_str` whose text happens to be a valid RFC 3339 date and
+    /// time are. specta still exports `DateTime` as `string`, so the generated types do not
+    /// reflect that.
+    SuperJson,
+}
+
+/// Values in a serialized payload that superjson needs to restore as something other than
+/// their JSON type, keyed by superjson's dotted path to the value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeAnnotations(Vec<(String, &'static str)>);
+
+const DATE: &str = "Date";
+const BIGINT: &str = "bigint";
+
+impl TypeAnnotations {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Moves every annotation under `prefix`, for when the annotated value is nested inside a
+    /// larger payload.
+    pub(crate) fn prefixed(&self, prefix: &str) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(path, annotation)| (join_path(prefix, path), *annotation))
+                .collect(),
+        )
+    }
+
+    fn push(&mut self, path: &str, annotation: &'static str) {
+        self.0.push((path.to_string(), annotation));
+    }
+}
+
+/// Serializes `value` the same way as `serde_json::to_value`, while recording the values that
+/// need a type annotation when sent through superjson.
+pub fn to_value<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<(Value, TypeAnnotations), serde_json::Error> {
+    let mut annotations = TypeAnnotations::default();
+    let value = value.serialize(AnnotatingSerializer {
+        path: String::new(),
+        annotations: &mut annotations,
+    })?;
+
+    Ok((value, annotations))
+}
+
+impl Transformer {
+    /// Encodes a payload produced by [`to_value`] for the client.
+    pub(crate) fn serialize(&self, value: Value, annotations: &TypeAnnotations) -> Value {
+        match self {
+            Transformer::None => value,
+            Transformer::SuperJson => superjson_serialize(value, annotations),
+        }
+    }
+
+    /// Encodes a `{ result: { data } }` or `{ error }` envelope for the client.
+    pub(crate) fn serialize_envelope(
+        &self,
+        envelope: &Value,
+        annotations: &TypeAnnotations,
+    ) -> Value {
+        if *self == Transformer::None {
+            return envelope.clone();
+        }
+
+        let mut envelope = envelope.clone();
+        if let Some(data) = envelope.pointer_mut("/result/data") {
+            *data = self.serialize(data.take(), annotations);
+        } else if let Some(error) = envelope.get_mut("error") {
+            *error = self.serialize(error.take(), &TypeAnnotations::default());
+        }
+
+        envelope
+    }
+
+    /// Decodes the input the client sent into plain JSON.
+    pub(crate) fn deserialize(&self, input: Option<Value>) -> Result<Option<Value>, RpcError> {
+        match (self, input) {
+            (Transformer::None, input) | (_, input @ None) => Ok(input),
+            (Transformer::SuperJson, Some(Value::Object(mut payload))) => {
+                let mut value = payload.remove("json").unwrap_or(Value::Null);
+                if let Some(values) = payload.get("meta").and_then(|meta| meta.get("values")) {
+                    apply_annotations(&mut value, values);
+                }
+
+                Ok(Some(value))
+            }
+            (Transformer::SuperJson, Some(_)) => Err(RpcError::new(
+                RpcStatus::BadRequest,
+                "Expected superjson input in the form { json, meta }".to_string(),
+            )),
+        }
+    }
+}
+
+fn superjson_serialize(mut value: Value, annotations: &TypeAnnotations) -> Value {
+    if annotations.is_empty() {
+        return json!({ "json": value });
+    }
+
+    let mut values = Map::new();
+    for (path, annotation) in &annotations.0 {
+        if *annotation == BIGINT
+            && let Some(target) = value_at_path(&mut value, path)
+            && target.is_number()
+        {
+            *target = Value::String(target.to_string());
+        }
+
+        if path.is_empty() {
+            return json!({ "json": value, "meta": { "values": [annotation], "v": 1 } });
+        }
+        values.insert(path.clone(), json!([annotation]));
+    }
+
+    json!({ "json": value, "meta": { "values": values, "v": 1 } })
+}
+
+/// Applies superjson `meta.values` to `value`. Annotations for types serde can read from their
+/// JSON form (like `Date` as an ISO string) leave the value as-is.
+fn apply_annotations(value: &mut Value, tree: &Value) {
+    match tree {
+        // `[type]` or `[type, { child path: tree }]`, children first
+        Value::Array(tree) => {
+            if let Some(children) = tree.get(1) {
+                apply_annotations(value, children);
+            }
+            if let Some(Value::String(annotation)) = tree.first() {
+                untransform(value, annotation);
+            }
+        }
+        Value::Object(children) => {
+            for (path, tree) in children {
+                if let Some(target) = value_at_path(value, path) {
+                    apply_annotations(target, tree);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn untransform(value: &mut Value, annotation: &str) {
+    match (annotation, &*value) {
+        (BIGINT, Value::String(number)) => {
+            if let Ok(number) = number.parse::<i64>() {
+                *value = json!(number);
+            } else if let Ok(number) = number.parse::<u64>() {
+                *value = json!(number);
+            }
+        }
+        ("undefined", _) => *value = Value::Null,
+        // Maps are sent as `[key, value]` pairs, serde expects an object
+        ("map", Value::Array(entries)) => {
+            let mut map = Map::new();
+            for entry in entries {
+                if let Some([key, entry_value]) = entry.as_array().map(Vec::as_slice) {
+                    let key = match key {
+                        Value::String(key) => key.clone(),
+                        key => key.to_string(),
+                    };
+                    map.insert(key, entry_value.clone());
+                }
+            }
+            *value = Value::Object(map);
+        }
+        _ => {}
+    }
+}
+
+fn value_at_path<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    let mut current = value;
+    for key in split_path(path) {
+        current = match current {
+            Value::Object(map) => map.get_mut(&key)?,
+            Value::Array(items) => items.get_mut(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(current)
+}
+
+fn split_path(path: &str) -> Vec<String> {
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    let mut segments = vec![String::new()];
+    let mut chars = path.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    segments.last_mut().unwrap().push(escaped);
+                }
+            }
+            '.' => segments.push(String::new()),
+            char => segments.last_mut().unwrap().push(char),
+        }
+    }
+
+    segments
+}
+
+fn escape_key(key: &str) -> String {
+    key.replace('\\', "\\\\").replace('.', "\\.")
+}
+
+fn join_path(parent: &str, child: &str) -> String {
+    match (parent.is_empty(), child.is_empty()) {
+        (true, _) => child.to_string(),
+        (_, true) => parent.to_string(),
+        _ => format!("{parent}.{child}"),
+    }
+}
+
+/// Checks for `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)` naming a real date and time,
+/// so that only text that `Date` would parse the same way is annotated.
+fn is_rfc3339_date_time(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let number = |range: std::ops::Range<usize>| {
+        bytes
+            .get(range)
+            .filter(|digits| digits.iter().all(u8::is_ascii_digit))
+            .map(|digits| {
+                digits
+                    .iter()
+                    .fold(0, |number, digit| number * 10 + u32::from(digit - b'0'))
+            })
+    };
+    let separated = |index: usize, separator: u8| bytes.get(index) == Some(&separator);
+
+    let (Some(year), Some(month), Some(day), Some(hour), Some(minute), Some(second)) = (
+        number(0..4),
+        number(5..7),
+        number(8..10),
+        number(11..13),
+        number(14..16),
+        number(17..19),
+    ) else {
+        return false;
+    };
+    let days_in_month = match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return false,
+    };
+    let date_time = separated(4, b'-')
+        && separated(7, b'-')
+        && matches!(bytes.get(10), Some(b'T' | b't'))
+        && separated(13, b':')
+        && separated(16, b':')
+        && (1..=days_in_month).contains(&day)
+        && hour < 24
+        && minute < 60
+        && second < 60;
+    if !date_time {
+        return false;
+    }
+
+    let mut offset = 19;
+    if separated(offset, b'.') {
+        offset += 1;
+        let fraction = bytes[offset..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        if fraction == 0 {
+            return false;
+        }
+        offset += fraction;
+    }
+
+    match &bytes[offset..] {
+        [b'Z' | b'z'] => true,
+        [b'+' | b'-', ..] => {
+            bytes.len() == offset + 6
+                && number(offset + 1..offset + 3).is_some_and(|hours| hours < 24)
+                && separated(offset + 3, b':')
+                && number(offset + 4..offset + 6).is_some_and(|minutes| minutes < 60)
+        }
+        _ => false,
+    }
+}
+
+struct AnnotatingSerializer<'a> {
+    path: String,
+    annotations: &'a mut TypeAnnotations,
+}
+
+impl<'a> AnnotatingSerializer<'a> {
+    fn child(&mut self, key: &str) -> AnnotatingSerializer<'_> {
+        AnnotatingSerializer {
+            path: join_path(&self.path, &escape_key(key)),
+            annotations: self.annotations,
+        }
+    }
+}
+
+impl<'a> ser::Serializer for AnnotatingSerializer<'a> {
+    type Ok = Value;
+    type Error = serde_json::Error;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer<'a>>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = VariantSerializer<MapSerializer<'a>>;
+
+    fn serialize_bool(self, v: bool) -> SerializeResult {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> SerializeResult {
+        Ok(json!(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> SerializeResult {
+        Ok(json!(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> SerializeResult {
+        Ok(json!(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> SerializeResult {
+        self.annotations.push(&self.path, BIGINT);
+        Ok(json!(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> SerializeResult {
+        self.annotations.push(&self.path, BIGINT);
+        serde_json::value::Serializer.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> SerializeResult {
+        Ok(json!(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> SerializeResult {
+        Ok(json!(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> SerializeResult {
+        Ok(json!(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> SerializeResult {
+        self.annotations.push(&self.path, BIGINT);
+        Ok(json!(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> SerializeResult {
+        self.annotations.push(&self.path, BIGINT);
+        serde_json::value::Serializer.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> SerializeResult {
+        serde_json::value::Serializer.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> SerializeResult {
+        serde_json::value::Serializer.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> SerializeResult {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> SerializeResult {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerializeResult {
+        serde_json::value::Serializer.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> SerializeResult {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SerializeResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerializeResult {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerializeResult {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> SerializeResult {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> SerializeResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> SerializeResult {
+        let value = value.serialize(self.child(variant))?;
+        let mut map = Map::new();
+        map.insert(variant.to_string(), value);
+        Ok(Value::Object(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            path: self.path,
+            annotations: self.annotations,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let path = join_path(&self.path, &escape_key(variant));
+        self.path = path;
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(MapSerializer {
+            map: Map::new(),
+            next_key: None,
+            path: self.path,
+            annotations: self.annotations,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        let path = join_path(&self.path, &escape_key(variant));
+        self.path = path;
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+
+    fn collect_str<T: std::fmt::Display + ?Sized>(self, value: &T) -> SerializeResult {
+        let value = value.to_string();
+        if is_rfc3339_date_time(&value) {
+            self.annotations.push(&self.path, DATE);
+        }
+
+        Ok(Value::String(value))
+    }
+}
+
+struct SeqSerializer<'a> {
+    items: Vec<Value>,
+    path: String,
+    annotations: &'a mut TypeAnnotations,
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let item = value.serialize(AnnotatingSerializer {
+            path: join_path(&self.path, &self.items.len().to_string()),
+            annotations: self.annotations,
+        })?;
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn end(self) -> SerializeResult {
+        Ok(Value::Array(self.items))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> SerializeResult {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> SerializeResult {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer<'a> {
+    map: Map<String, Value>,
+    next_key: Option<String>,
+    path: String,
+    annotations: &'a mut TypeAnnotations,
+}
+
+impl MapSerializer<'_> {
+    fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        let value = value.serialize(AnnotatingSerializer {
+            path: join_path(&self.path, &escape_key(&key)),
+            annotations: self.annotations,
+        })?;
+        self.map.insert(key, value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.next_key = Some(match key.serialize(serde_json::value::Serializer)? {
+            Value::String(key) => key,
+            Value::Number(key) => key.to_string(),
+            Value::Bool(key) => key.to_string(),
+            _ => return Err(ser::Error::custom("key must be a string")),
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| <serde_json::Error as ser::Error>::custom("value without a key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> SerializeResult {
+        Ok(Value::Object(self.map))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer<'_> {
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> SerializeResult {
+        Ok(Value::Object(self.map))
+    }
+}
+
+/// Wraps the output of an enum variant as `{ variant: value }`.
+struct VariantSerializer<T> {
+    variant: &'static str,
+    inner: T,
+}
+
+impl<T: ser::SerializeSeq<Ok = Value, Error = serde_json::Error>> ser::SerializeTupleVariant
+    for VariantSerializer<T>
+{
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<F: Serialize + ?Sized>(&mut self, value: &F) -> Result<(), Self::Error> {
+        self.inner.serialize_element(value)
+    }
+
+    fn end(self) -> SerializeResult {
+        let mut map = Map::new();
+        map.insert(self.variant.to_string(), self.inner.end()?);
+        Ok(Value::Object(map))
+    }
+}
+
+impl<T: ser::SerializeStruct<Ok = Value, Error = serde_json::Error>> ser::SerializeStructVariant
+    for VariantSerializer<T>
+{
+    type Ok = Value;
+    type Error = serde_json::Error;
+
+    fn serialize_field<F: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &F,
+    ) -> Result<(), Self::Error> {
+        self.inner.serialize_field(key, value)
+    }
+
+    fn end(self) -> SerializeResult {
+        let mut map = Map::new();
+        map.insert(self.variant.to_string(), self.inner.end()?);
+        Ok(Value::Object(map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// Serialized through `collect_str`, like chrono's `DateTime`.
+    struct Displayed(&'static str);
+
+    impl Serialize for Displayed {
+        fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self.0)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Event {
+        id: u64,
+        count: u32,
+        created_at: Displayed,
+        note: String,
+    }
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Value {
+        let (value, annotations) = to_value(value).unwrap();
+        Transformer::SuperJson.serialize(value, &annotations)
+    }
+
+    fn decode(payload: Value) -> Value {
+        Transformer::SuperJson
+            .deserialize(Some(payload))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn annotates_bigints_and_dates() {
+        let event = Event {
+            id: 1,
+            count: 2,
+            created_at: Displayed("2024-01-02T03:04:05.678Z"),
+            note: "2024-01-02T03:04:05Z".to_string(),
+        };
+        assert_eq!(
+            encode(&event),
+            json!({
+                "json": {
+                    "id": "1",
+                    "count": 2,
+                    "created_at": "2024-01-02T03:04:05.678Z",
+                    "note": "2024-01-02T03:04:05Z",
+                },
+                "meta": {
+                    "values": { "id": ["bigint"], "created_at": ["Date"] },
+                    "v": 1,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn annotates_nested_values_with_escaped_paths() {
+        let value = BTreeMap::from([("a.b", vec![5u64]), ("c\\d", vec![8]), ("e", vec![6, 7])]);
+        assert_eq!(
+            encode(&value),
+            json!({
+                "json": { "a.b": ["5"], "c\\d": ["8"], "e": ["6", "7"] },
+                "meta": {
+                    "values": {
+                        "a\\.b.0": ["bigint"],
+                        "c\\\\d.0": ["bigint"],
+                        "e.0": ["bigint"],
+                        "e.1": ["bigint"],
+                    },
+                    "v": 1,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn annotates_root_values() {
+        assert_eq!(
+            encode(&5u64),
+            json!({ "json": "5", "meta": { "values": ["bigint"], "v": 1 } })
+        );
+        assert_eq!(
+            encode(&Displayed("2024-01-02T03:04:05+01:00")),
+            json!({
+                "json": "2024-01-02T03:04:05+01:00",
+                "meta": { "values": ["Date"], "v": 1 },
+            })
+        );
+        assert_eq!(encode("text"), json!({ "json": "text" }));
+    }
+
+    #[test]
+    fn only_annotates_real_dates() {
+        for text in [
+            "2024-01-02T03:04:05Z",
+            "2024-02-29T23:59:59.999999+05:30",
+            "2024-12-31t00:00:00z",
+        ] {
+            assert!(is_rfc3339_date_time(text), "`{text}` should be a date");
+        }
+
+        for text in [
+            "2024-01-02",
+            "2024-01-02T03:04:05",
+            "2024-01-02 03:04:05Z",
+            "2023-02-29T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-01-32T00:00:00Z",
+            "2024-01-02T24:00:00Z",
+            "2024-01-02T03:60:00Z",
+            "2024-01-02T03:04:60Z",
+            "2024-01-02T03:04:05.Z",
+            "2024-01-02T03:04:05+1:00",
+            "2024-01-02T03:04:05+24:00",
+            "2024-01-02T03:04:05Zjunk",
+        ] {
+            assert!(!is_rfc3339_date_time(text), "`{text}` should not be a date");
+        }
+
+        assert_eq!(
+            encode(&Displayed("2024-13-01T00:00:00Z")),
+            json!({ "json": "2024-13-01T00:00:00Z" })
+        );
+    }
+
+    #[test]
+    fn decodes_bigints_and_leaves_dates() {
+        let payload = json!({
+            "json": { "id": "9007199254740993", "min": "-5", "created_at": "2024-01-02T03:04:05.678Z" },
+            "meta": {
+                "values": { "id": ["bigint"], "min": ["bigint"], "created_at": ["Date"] },
+                "v": 1,
+            },
+        });
+        assert_eq!(
+            decode(payload),
+            json!({ "id": 9007199254740993u64, "min": -5, "created_at": "2024-01-02T03:04:05.678Z" })
+        );
+        assert_eq!(
+            decode(json!({ "json": "5", "meta": { "values": ["bigint"], "v": 1 } })),
+            json!(5)
+        );
+    }
+
+    #[test]
+    fn decodes_undefined_as_null() {
+        let payload = json!({
+            "json": [null, 1],
+            "meta": { "values": { "0": ["undefined"] }, "v": 1 },
+        });
+        assert_eq!(decode(payload), json!([null, 1]));
+    }
+
+    #[test]
+    fn decodes_maps_after_their_entries() {
+        // superjson.serialize({ "a.b": new Map([[1n, 2n]]) })
+        let payload = json!({
+            "json": { "a.b": [["1", "2"]] },
+            "meta": {
+                "values": { "a\\.b": ["map", { "0.0": ["bigint"], "0.1": ["bigint"] }] },
+                "v": 1,
+            },
+        });
+        assert_eq!(decode(payload), json!({ "a.b": { "1": 2 } }));
+    }
+
+    #[test]
+    fn rejects_input_outside_an_envelope() {
+        assert!(Transformer::SuperJson.deserialize(Some(json!(5))).is_err());
+        assert_eq!(decode(json!({ "json": { "id": 1 } })), json!({ "id": 1 }));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::errors::{RpcError, RpcStatus};
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    extract::FromRequestParts,
+    http::{HeaderMap, StatusCode, header, request::Parts},
+};
+use futures_util::StreamExt;
+use multer::{Constraints, Multipart, SizeLimit};
+use specta::{
+    Generics, TypeCollection,
+    datatype::{DataType, GenericType},
+};
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+};
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// A `multipart/form-data` mutation input, sent by tRPC clients when a mutation is called
+/// with a `FormData` object.
+///
+/// The body is read as it arrives rather than buffered, so files are only held in memory if the
+/// procedure asks for them with [`File::bytes`] or [`File::text`]. Add it as an `#[extract]`
+/// argument to a mutation in place of any other input. The body is capped at the procedure's
+/// `max_body_size`, which defaults to 1 MB.
+pub struct FormData {
+    multipart: Multipart<'static>,
+}
+
+impl FormData {
+    /// Reads `body` as `multipart/form-data`, failing if it is larger than `max_size` bytes.
+    pub(crate) fn from_body(
+        headers: &HeaderMap,
+        body: Body,
+        max_size: usize,
+    ) -> Result<Self, RpcError> {
+        let boundary = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| multer::parse_boundary(value).ok())
+            .ok_or_else(|| {
+                RpcError::new(
+                    RpcStatus::UnsupportedMediaType,
+                    "Expected a multipart/form-data body".to_string(),
+                )
+            })?;
+
+        let constraints =
+            Constraints::new().size_limit(SizeLimit::new().whole_stream(max_size as u64));
+
+        Ok(Self {
+            multipart: Multipart::with_constraints(body.into_data_stream(), boundary, constraints),
+        })
+    }
+
+    /// Waits for the next entry in the form. Each entry must be dropped before asking for the
+    /// next one.
+    pub async fn next_field(&mut self) -> Result<Option<File>, RpcError> {
+        let field = self.multipart.next_field().await.map_err(upload_error)?;
+        Ok(field.map(|field| File { field }))
+    }
+}
+
+impl specta::Type for FormData {
+    fn inline(_type_map: &mut TypeCollection, _generics: Generics) -> DataType {
+        DataType::Generic(GenericType::from(Cow::Borrowed("FormData")))
+    }
+}
+
+/// A request body read by the router, waiting to be picked up by the procedure's [`FormData`]
+/// or [`ByteStream`] argument.
+pub(crate) struct PendingBody<T>(Arc<Mutex<Option<T>>>);
+
+impl<T> PendingBody<T> {
+    pub(crate) fn new(body: T) -> Self {
+        Self(Arc::new(Mutex::new(Some(body))))
+    }
+
+    fn take(parts: &Parts) -> Option<T>
+    where
+        T: Send + 'static,
+    {
+        parts
+            .extensions
+            .get::<PendingBody<T>>()
+            .and_then(|pending| pending.0.lock().ok()?.take())
+    }
+}
+
+impl<T> Clone for PendingBody<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for FormData {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        PendingBody::take(parts).ok_or((
+            StatusCode::BAD_REQUEST,
+            "FormData is only available to mutations sent as multipart/form-data",
+        ))
+    }
+}
+
+/// An entry in a [`FormData`] input. Files have a [`file_name`](File::file_name), other
+/// entries are plain text.
+pub struct File {
+    field: multer::Field<'static>,
+}
+
+impl File {
+    /// The key the entry was added to the `FormData` with.
+    pub fn name(&self) -> Option<&str> {
+        self.field.name()
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.field.file_name()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.field.content_type().map(|mime| mime.as_ref())
+    }
+
+    /// Waits for the next chunk of the entry, or `None` once all of it has been read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, RpcError> {
+        self.field.chunk().await.map_err(upload_error)
+    }
+
+    /// Reads the rest of the entry into memory.
+    pub async fn bytes(self) -> Result<Bytes, RpcError> {
+        self.field.bytes().await.map_err(upload_error)
+    }
+
+    /// Reads the rest of the entry into memory as text.
+    pub async fn text(self) -> Result<String, RpcError> {
+        self.field.text().await.map_err(upload_error)
+    }
+}
+
+/// An `application/octet-stream` mutation input, sent by tRPC clients when a mutation is called
+/// with a `Blob`, `File` or `Uint8Array`.
+///
+/// Like [`FormData`], the body is read as it arrives and is capped at the procedure's
+/// `max_body_size`. Add it as an `#[extract]` argument to a mutation in place of any other
+/// input.
+pub struct ByteStream {
+    body: BodyDataStream,
+    read: usize,
+    max_size: usize,
+}
+
+impl ByteStream {
+    /// Reads `body` as `application/octet-stream`, failing if it is larger than `max_size` bytes.
+    pub(crate) fn from_body(
+        headers: &HeaderMap,
+        body: Body,
+        max_size: usize,
+    ) -> Result<Self, RpcError> {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !content_type.starts_with(OCTET_STREAM) {
+            return Err(RpcError::new(
+                RpcStatus::UnsupportedMediaType,
+                format!("Expected an {} body", OCTET_STREAM),
+            ));
+        }
+
+        Ok(Self {
+            body: body.into_data_stream(),
+            read: 0,
+            max_size,
+        })
+    }
+
+    /// Waits for the next chunk of the body, or `None` once all of it has been read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, RpcError> {
+        let Some(chunk) = self.body.next().await else {
+            return Ok(None);
+        };
+
+        let chunk = chunk.map_err(|err| {
+            RpcError::new(
+                RpcStatus::BadRequest,
+                format!("Failed to read request body: {}", err),
+            )
+        })?;
+
+        self.read += chunk.len();
+        if self.read > self.max_size {
+            return Err(RpcError::new(
+                RpcStatus::PayloadTooLarge,
+                format!(
+                    "Request body exceeds the maximum size of {} bytes",
+                    self.max_size
+                ),
+            ));
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// Reads the rest of the body into memory.
+    pub async fn bytes(mut self) -> Result<Bytes, RpcError> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(Bytes::from(bytes))
+    }
+}
+
+impl specta::Type for ByteStream {
+    fn inline(_type_map: &mut TypeCollection, _generics: Generics) -> DataType {
+        DataType::Generic(GenericType::from(Cow::Borrowed("Blob | Uint8Array")))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ByteStream {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        PendingBody::take(parts).ok_or((
+            StatusCode::BAD_REQUEST,
+            "ByteStream is only available to mutations sent as application/octet-stream",
+        ))
+    }
+}
+
+fn upload_error(err: multer::Error) -> RpcError {
+    let status = match err {
+        multer::Error::StreamSizeExceeded { .. } | multer::Error::FieldSizeExceeded { .. } => {
+            RpcStatus::PayloadTooLarge
+        }
+        _ => RpcStatus::BadRequest,
+    };
+
+    RpcError::new(status, format!("Failed to read form data: {}", err))
+}
@@ -0,0 +1,489 @@
+use crate::errors::{RpcError, RpcStatus};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use serde_path_to_error::Segment;
+
+/// A problem with one field of a procedure's input, sent to the client in `error.data.issues`
+/// like the issues of a zod error.
+#[derive(Clone, Debug, Serialize, specta::Type)]
+pub struct ValidationIssue {
+    /// The path to the field in the input, e.g. `["address", "lines", 0]`. Empty when the
+    /// input as a whole is invalid.
+    pub path: Vec<PathSegment>,
+    /// The type or value the field should have, when it is known.
+    pub expected: Option<String>,
+    pub message: String,
+}
+
+/// An object key or array index in the path of a [`ValidationIssue`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, specta::Type)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl ValidationIssue {
+    pub fn new(path: Vec<PathSegment>, message: impl Into<String>) -> Self {
+        Self {
+            path,
+            expected: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn expected(mut self, expected: impl Into<String>) -> Self {
+        self.expected = Some(expected.into());
+        self
+    }
+
+    fn from_serde(path: &serde_path_to_error::Path, err: &serde_json::Error) -> Self {
+        let mut path: Vec<_> = path
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Seq { index } => Some(PathSegment::Index(*index)),
+                Segment::Map { key } => Some(PathSegment::Key(key.clone())),
+                Segment::Enum { variant } => Some(PathSegment::Key(variant.clone())),
+                Segment::Unknown => None,
+            })
+            .collect();
+
+        let message = err.to_string();
+        // serde reports missing fields on the object containing them
+        if let Some(field) = message
+            .strip_prefix("missing field `")
+            .and_then(|field| field.strip_suffix('`'))
+        {
+            path.push(PathSegment::Key(field.to_string()));
+            return Self::new(path, "Required");
+        }
+
+        // The value in the message may itself contain ", expected "
+        match message.rsplit_once(", expected ") {
+            Some((_, expected)) => {
+                let expected = expected.to_string();
+                Self::new(path, message).expected(expected)
+            }
+            None => Self::new(path, message),
+        }
+    }
+}
+
+/// Deserializes a procedure's input, reporting where it failed as a [`ValidationIssue`].
+#[doc(hidden)]
+pub fn deserialize_input<T: DeserializeOwned>(
+    input: Option<Value>,
+    name: &str,
+) -> Result<T, RpcError> {
+    let Some(input) = input else {
+        return Err(
+            RpcError::new(RpcStatus::BadRequest, "Missing input arguments".to_string())
+                .with_issues(vec![
+                    ValidationIssue::new(Vec::new(), "Required").expected("object"),
+                ]),
+        );
+    };
+
+    serde_path_to_error::deserialize(input).map_err(|err| {
+        let issue = ValidationIssue::from_serde(err.path(), err.inner());
+        RpcError::new(
+            RpcStatus::BadRequest,
+            format!("Failed to deserialize input for '{}': {}", name, err),
+        )
+        .with_issues(vec![issue])
+    })
+}
+
+/// Deserializes the input of a procedure with an `#[input]` argument. A missing input is read
+/// as `null`, so `Option` inputs can be left out.
+#[doc(hidden)]
+pub fn deserialize_value<T: DeserializeOwned>(
+    input: Option<Value>,
+    name: &str,
+) -> Result<T, RpcError> {
+    deserialize_input(Some(input.unwrap_or(Value::Null)), name)
+}
+
+/// A `#[validate(..)]` rule on an input argument, kept on the procedure for the generated client.
+/// Rules of an `#[input]` argument are kept for the field `""`, as they apply to the whole input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Length {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+    Range {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Email,
+}
+
+/// Values that `#[validate(length(..))]` can check. Missing optional values are not checked.
+pub trait ValidateLength {
+    fn validated_length(&self) -> Option<usize>;
+}
+
+impl ValidateLength for String {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.chars().count())
+    }
+}
+
+impl<T> ValidateLength for Vec<T> {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<T: ValidateLength> ValidateLength for Option<T> {
+    fn validated_length(&self) -> Option<usize> {
+        self.as_ref().and_then(T::validated_length)
+    }
+}
+
+/// Values that `#[validate(range(..))]` can check. Missing optional values are not checked.
+///
+/// Bounds are constants of the value's own number type, so integers too large for an `f64`
+/// are still checked exactly, and `f32`/`f64` values take float bounds like `0.0`.
+///
+/// ```no_run
+/// #[juno::rpc(query)]
+/// async fn list_members(#[validate(range(min = 1, max = 100))] limit: u8) -> Vec<String> {
+///     Vec::new()
+/// }
+/// ```
+///
+/// A bound that doesn't fit the value's type fails to compile, rather than being checked as
+/// some other number:
+///
+/// ```compile_fail
+/// #[juno::rpc(query)]
+/// async fn list_members(#[validate(range(max = 300))] limit: u8) -> Vec<String> {
+///     Vec::new()
+/// }
+/// ```
+///
+/// ```compile_fail,E0308
+/// #[juno::rpc(query)]
+/// async fn list_members(#[validate(range(min = 1.5))] limit: u64) -> Vec<String> {
+///     Vec::new()
+/// }
+/// ```
+pub trait ValidateRange {
+    type Number: PartialOrd + std::fmt::Display + Copy;
+
+    fn validated_number(&self) -> Option<Self::Number>;
+}
+
+macro_rules! impl_validate_range {
+    ($($ty:ty),*) => {
+        $(impl ValidateRange for $ty {
+            type Number = $ty;
+
+            fn validated_number(&self) -> Option<$ty> {
+                Some(*self)
+            }
+        })*
+    };
+}
+
+impl_validate_range!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+impl<T: ValidateRange> ValidateRange for Option<T> {
+    type Number = T::Number;
+
+    fn validated_number(&self) -> Option<T::Number> {
+        self.as_ref().and_then(T::validated_number)
+    }
+}
+
+/// Values that `#[validate(email)]` can check. Missing optional values are not checked.
+pub trait ValidateEmail {
+    fn validated_str(&self) -> Option<&str>;
+}
+
+impl ValidateEmail for String {
+    fn validated_str(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<T: ValidateEmail> ValidateEmail for Option<T> {
+    fn validated_str(&self) -> Option<&str> {
+        self.as_ref().and_then(T::validated_str)
+    }
+}
+
+/// The path of `field` in the input, where the empty field is the whole input of an `#[input]`
+/// argument.
+fn field_path(field: &str) -> Vec<PathSegment> {
+    match field {
+        "" => Vec::new(),
+        field => vec![PathSegment::Key(field.to_string())],
+    }
+}
+
+/// Checks `#[validate(length(min = .., max = ..))]`, counting characters for strings.
+#[doc(hidden)]
+pub fn length<T: ValidateLength>(
+    field: &str,
+    value: &T,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Option<ValidationIssue> {
+    let length = value.validated_length()?;
+    let path = field_path(field);
+    if let Some(min) = min.filter(|min| length < *min) {
+        return Some(
+            ValidationIssue::new(path, format!("Must have a length of at least {}", min))
+                .expected(format!("length >= {}", min)),
+        );
+    }
+    if let Some(max) = max.filter(|max| length > *max) {
+        return Some(
+            ValidationIssue::new(path, format!("Must have a length of at most {}", max))
+                .expected(format!("length <= {}", max)),
+        );
+    }
+    None
+}
+
+/// Checks `#[validate(range(min = .., max = ..))]`, both bounds being inclusive.
+#[doc(hidden)]
+pub fn range<T: ValidateRange>(
+    field: &str,
+    value: &T,
+    min: Option<T::Number>,
+    max: Option<T::Number>,
+) -> Option<ValidationIssue> {
+    let number = value.validated_number()?;
+    let path = field_path(field);
+    if let Some(min) = min.filter(|min| number < *min) {
+        return Some(
+            ValidationIssue::new(path, format!("Must be at least {}", min))
+                .expected(format!(">= {}", min)),
+        );
+    }
+    if let Some(max) = max.filter(|max| number > *max) {
+        return Some(
+            ValidationIssue::new(path, format!("Must be at most {}", max))
+                .expected(format!("<= {}", max)),
+        );
+    }
+    None
+}
+
+/// Checks `#[validate(email)]`. This only catches obvious mistakes, the only real check is
+/// sending an email to the address.
+#[doc(hidden)]
+pub fn email<T: ValidateEmail>(field: &str, value: &T) -> Option<ValidationIssue> {
+    let value = value.validated_str()?;
+    let valid = value.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty()
+            && !domain.contains('@')
+            && domain
+                .split_once('.')
+                .is_some_and(|(name, tld)| !name.is_empty() && !tld.is_empty())
+            && !domain.ends_with('.')
+    }) && !value.chars().any(char::is_whitespace);
+
+    (!valid)
+        .then(|| ValidationIssue::new(field_path(field), "Invalid email address").expected("email"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Role {
+        Admin,
+        Member,
+    }
+
+    // `Item` and `Input` are only deserialized to check the issues reported when that fails, so
+    // their fields are never read.
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Item {
+        id: u32,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Input {
+        name: String,
+        #[serde(default)]
+        role: Option<Role>,
+        #[serde(default)]
+        items: Vec<Item>,
+    }
+
+    fn input_issue(input: Value) -> ValidationIssue {
+        let err = deserialize_input::<Input>(Some(input), "Input").unwrap_err();
+        assert_eq!(err.status, RpcStatus::BadRequest);
+        assert_eq!(err.issues.len(), 1);
+        err.issues.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn missing_fields_are_required() {
+        let issue = input_issue(json!({}));
+        assert_eq!(issue.path, vec![PathSegment::Key("name".to_string())]);
+        assert_eq!(issue.message, "Required");
+        assert_eq!(issue.expected, None);
+    }
+
+    #[test]
+    fn missing_input_is_required() {
+        let err = deserialize_input::<Input>(None, "Input").unwrap_err();
+        assert_eq!(err.issues[0].path, Vec::new());
+        assert_eq!(err.issues[0].expected.as_deref(), Some("object"));
+    }
+
+    #[test]
+    fn wrong_types_report_the_expected_type() {
+        let issue = input_issue(json!({ "name": 5 }));
+        assert_eq!(issue.path, vec![PathSegment::Key("name".to_string())]);
+        assert_eq!(
+            issue.message,
+            "invalid type: integer `5`, expected a string"
+        );
+        assert_eq!(issue.expected.as_deref(), Some("a string"));
+
+        // Only the last ", expected " is serde's
+        let issue = input_issue(json!({ "name": "a", "items": [{ "id": "1, expected 2" }] }));
+        assert_eq!(issue.expected.as_deref(), Some("u32"));
+    }
+
+    #[test]
+    fn unknown_variants_report_the_variants() {
+        let issue = input_issue(json!({ "name": "a", "role": "owner" }));
+        assert_eq!(issue.path, vec![PathSegment::Key("role".to_string())]);
+        assert_eq!(issue.expected.as_deref(), Some("`admin` or `member`"));
+    }
+
+    #[test]
+    fn nested_paths_include_array_indexes() {
+        let issue = input_issue(json!({ "name": "a", "items": [{ "id": 1 }, {}] }));
+        assert_eq!(
+            issue.path,
+            vec![
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(1),
+                PathSegment::Key("id".to_string()),
+            ]
+        );
+        assert_eq!(issue.message, "Required");
+
+        let issue = input_issue(json!({ "name": "a", "items": [{ "id": -1 }] }));
+        assert_eq!(
+            issue.path,
+            vec![
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(0),
+                PathSegment::Key("id".to_string()),
+            ]
+        );
+        assert_eq!(issue.expected.as_deref(), Some("u32"));
+    }
+
+    #[test]
+    fn email_accepts_plain_addresses() {
+        assert!(email("email", &"user@example.com".to_string()).is_none());
+        assert!(email("email", &"first.last+tag@mail.example.co.uk".to_string()).is_none());
+    }
+
+    #[test]
+    fn email_rejects_obvious_mistakes() {
+        for invalid in [
+            "",
+            "user",
+            "@example.com",
+            "user@",
+            "user@example",
+            "user@.com",
+            "user@example.",
+            "user@@example.com",
+            "user@exa@mple.com",
+            "us er@example.com",
+            "user@example.com ",
+        ] {
+            let issue = email("email", &invalid.to_string())
+                .unwrap_or_else(|| panic!("`{invalid}` should be invalid"));
+            assert_eq!(issue.path, vec![PathSegment::Key("email".to_string())]);
+            assert_eq!(issue.expected.as_deref(), Some("email"));
+        }
+    }
+
+    #[test]
+    fn length_counts_characters_not_bytes() {
+        // 4 characters, 8 bytes
+        let nick = "ñößé".to_string();
+        assert!(length("nick", &nick, Some(4), Some(4)).is_none());
+        assert!(length("nick", &nick, Some(5), None).is_some());
+        assert!(length("nick", &nick, None, Some(3)).is_some());
+    }
+
+    #[test]
+    fn length_bounds_are_inclusive() {
+        let tags = vec![1, 2, 3];
+        assert!(length("tags", &tags, Some(3), Some(3)).is_none());
+
+        let issue = length("tags", &tags, Some(4), None).unwrap();
+        assert_eq!(issue.message, "Must have a length of at least 4");
+        assert_eq!(issue.expected.as_deref(), Some("length >= 4"));
+
+        let issue = length("tags", &tags, None, Some(2)).unwrap();
+        assert_eq!(issue.message, "Must have a length of at most 2");
+        assert_eq!(issue.expected.as_deref(), Some("length <= 2"));
+    }
+
+    #[test]
+    fn range_bounds_are_inclusive() {
+        assert!(range("age", &18u8, Some(18), Some(18)).is_none());
+        assert!(range("age", &-1i32, Some(0), None).is_some());
+        assert!(range("score", &0.5f64, Some(0.0), Some(1.0)).is_none());
+        assert!(range("score", &1.01f64, None, Some(1.0)).is_some());
+
+        let issue = range("age", &17u8, Some(18), None).unwrap();
+        assert_eq!(issue.message, "Must be at least 18");
+        assert_eq!(issue.expected.as_deref(), Some(">= 18"));
+
+        let issue = range("age", &121u8, None, Some(120)).unwrap();
+        assert_eq!(issue.message, "Must be at most 120");
+        assert_eq!(issue.expected.as_deref(), Some("<= 120"));
+    }
+
+    #[test]
+    fn range_compares_large_integers_exactly() {
+        // Both round to the same f64
+        let max = (1u64 << 53) + 1;
+        assert!(range("id", &max, None, Some(max)).is_none());
+        assert!(range("id", &(max + 1), None, Some(max)).is_some());
+        assert!(range("id", &i64::MIN, Some(i64::MIN + 1), None).is_some());
+    }
+
+    #[test]
+    fn whole_input_issues_have_an_empty_path() {
+        let issue = range("", &0u64, Some(1), None).unwrap();
+        assert!(issue.path.is_empty());
+    }
+
+    #[test]
+    fn missing_optional_values_are_not_checked() {
+        assert!(length::<Option<String>>("nick", &None, Some(1), None).is_none());
+        assert!(range::<Option<u32>>("age", &None, Some(18), None).is_none());
+        assert!(email::<Option<String>>("email", &None).is_none());
+
+        assert!(length("nick", &Some(String::new()), Some(1), None).is_some());
+        assert!(range("age", &Some(17u32), Some(18), None).is_some());
+        assert!(email("email", &Some("user".to_string())).is_some());
+    }
+}
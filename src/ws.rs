@@ -0,0 +1,333 @@
+use crate::{
+    errors::{RpcError, RpcStatus},
+    response::{IntoRpcResponse, RpcResponse},
+    router::{RpcRouter, RpcType},
+    subscription::LastEventId,
+    transformer::{Transformer, TypeAnnotations},
+};
+use axum::{
+    body::Body,
+    extract::{
+        FromRequestParts, Request, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// A message sent by tRPC's `wsLink`.
+#[derive(Deserialize)]
+struct WsRequest {
+    #[serde(default)]
+    id: Value,
+    jsonrpc: Option<String>,
+    method: String,
+    params: Option<WsParams>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WsParams {
+    path: String,
+    input: Option<Value>,
+    last_event_id: Option<String>,
+}
+
+/// Sends messages for a single operation, tagged with the id the client gave it.
+#[derive(Clone)]
+struct Responder {
+    sender: mpsc::UnboundedSender<Message>,
+    id: Value,
+    jsonrpc: Option<String>,
+    transformer: Transformer,
+}
+
+impl Responder {
+    fn send(&self, envelope: Value) {
+        let mut message = json!({ "id": self.id });
+        if let Some(jsonrpc) = &self.jsonrpc {
+            message["jsonrpc"] = json!(jsonrpc);
+        }
+
+        if let (Value::Object(message), Value::Object(envelope)) = (&mut message, envelope) {
+            message.extend(envelope);
+        }
+
+        // The socket closing drops the receiver, at which point there is no one left to tell.
+        let _ = self.sender.send(Message::text(message.to_string()));
+    }
+
+    fn send_result(&self, result: Value) {
+        self.send(json!({ "result": result }));
+    }
+
+    fn send_data(&self, data: Value, annotations: &TypeAnnotations, id: Option<String>) {
+        let mut result = json!({
+            "type": "data",
+            "data": self.transformer.serialize(data, annotations),
+        });
+        if let Some(id) = id {
+            result["id"] = json!(id);
+        }
+        self.send_result(result);
+    }
+
+    fn send_error(&self, err: RpcError) {
+        self.send_response(err.into_rpc_response());
+    }
+
+    fn send_response(&self, response: RpcResponse) {
+        if let Some(envelope) = response.encoded_value(self.transformer) {
+            self.send(with_data_type(envelope));
+        }
+    }
+}
+
+/// Upgrades the request to a WebSocket speaking the tRPC `wsLink` protocol.
+pub(crate) async fn upgrade<S>(rpc: Arc<RpcRouter<S>>, state: S, req: Request<Body>) -> Response
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let (mut parts, _body) = req.into_parts();
+    let upgrade = match WebSocketUpgrade::from_request_parts(&mut parts, &state).await {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    upgrade.on_upgrade(move |socket| serve_socket(rpc, state, parts, socket))
+}
+
+/// Runs every operation sent over `socket` concurrently. Each call gets a copy of the upgrade
+/// request's parts, so extractors see the same headers as they would over HTTP, and they all
+/// share the context created for the connection.
+async fn serve_socket<S>(rpc: Arc<RpcRouter<S>>, state: S, mut parts: Parts, socket: WebSocket)
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let (mut sink, mut incoming) = socket.split();
+    let (sender, mut outgoing) = mpsc::unbounded_channel::<Message>();
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Without a context no operation can run, so the connection is closed like tRPC does
+    if let Err(err) = rpc.create_context(&state, &mut parts).await {
+        let responder = Responder {
+            sender: sender.clone(),
+            id: Value::Null,
+            jsonrpc: None,
+            transformer: rpc.transformer,
+        };
+        responder.send_error(err);
+        let _ = sender.send(Message::Close(None));
+        return;
+    }
+
+    let mut subscriptions: HashMap<String, JoinHandle<()>> = HashMap::new();
+    while let Some(Ok(message)) = incoming.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        if text.as_str() == "PING" {
+            let _ = sender.send(Message::text("PONG"));
+            continue;
+        }
+
+        let requests = match serde_json::from_str::<Value>(text.as_str()) {
+            Ok(Value::Array(requests)) => requests,
+            Ok(request) => vec![request],
+            Err(err) => {
+                let responder = Responder {
+                    sender: sender.clone(),
+                    id: Value::Null,
+                    jsonrpc: None,
+                    transformer: rpc.transformer,
+                };
+                responder.send_error(RpcError::new(
+                    RpcStatus::ParseError,
+                    format!("Invalid JSON in message: {}", err),
+                ));
+                continue;
+            }
+        };
+
+        for request in requests {
+            handle_message(&rpc, &state, &parts, &sender, &mut subscriptions, request);
+        }
+    }
+
+    for (_, subscription) in subscriptions {
+        subscription.abort();
+    }
+}
+
+fn handle_message<S>(
+    rpc: &RpcRouter<S>,
+    state: &S,
+    parts: &Parts,
+    sender: &mpsc::UnboundedSender<Message>,
+    subscriptions: &mut HashMap<String, JoinHandle<()>>,
+    request: Value,
+) where
+    S: Clone + Send + Sync + 'static,
+{
+    let request = match serde_json::from_value::<WsRequest>(request) {
+        Ok(request) => request,
+        Err(err) => {
+            let responder = Responder {
+                sender: sender.clone(),
+                id: Value::Null,
+                jsonrpc: None,
+                transformer: rpc.transformer,
+            };
+            responder.send_error(RpcError::new(
+                RpcStatus::BadRequest,
+                format!("Invalid message: {}", err),
+            ));
+            return;
+        }
+    };
+
+    let responder = Responder {
+        sender: sender.clone(),
+        id: request.id,
+        jsonrpc: request.jsonrpc,
+        transformer: rpc.transformer,
+    };
+    let subscription_key = responder.id.to_string();
+
+    let rpc_type = match request.method.as_str() {
+        "query" => RpcType::Query,
+        "mutation" => RpcType::Mutation,
+        "subscription" => RpcType::Subscription,
+        "subscription.stop" => {
+            if let Some(subscription) = subscriptions.remove(&subscription_key) {
+                subscription.abort();
+                responder.send_result(json!({ "type": "stopped" }));
+            }
+            return;
+        }
+        // Sent before anything else when the client is configured with `connectionParams`.
+        "connectionParams" => return,
+        method => {
+            responder.send_error(RpcError::new(
+                RpcStatus::MethodNotSupported,
+                format!("Unknown method `{}`", method),
+            ));
+            return;
+        }
+    };
+
+    let Some(params) = request.params else {
+        responder.send_error(RpcError::new(
+            RpcStatus::BadRequest,
+            "Missing params".to_string(),
+        ));
+        return;
+    };
+
+    let procedure = match rpc.procedure(&params.path) {
+        Ok(procedure) => procedure,
+        Err(err) => {
+            responder.send_error(err);
+            return;
+        }
+    };
+
+    // From here on errors are about the procedure, so they go through the error formatter
+    let send_error = |err| responder.send_response(rpc.procedure_error(err, procedure, None));
+    if procedure.rpc_type != rpc_type {
+        send_error(RpcError::new(
+            RpcStatus::MethodNotSupported,
+            format!("`{}` cannot be called as a {}", params.path, request.method),
+        ));
+        return;
+    }
+
+    if !procedure.uses_json() {
+        send_error(RpcError::new(
+            RpcStatus::BadRequest,
+            format!(
+                "`{}` does not use JSON and cannot be called over WebSocket",
+                params.path
+            ),
+        ));
+        return;
+    }
+
+    let mut parts = parts.clone();
+    if rpc_type == RpcType::Subscription {
+        if subscriptions
+            .get(&subscription_key)
+            .is_some_and(|subscription| !subscription.is_finished())
+        {
+            send_error(RpcError::new(
+                RpcStatus::BadRequest,
+                format!("Duplicate subscription id {}", subscription_key),
+            ));
+            return;
+        }
+
+        parts.extensions.insert(LastEventId(params.last_event_id));
+    }
+
+    let input = match rpc.transformer.deserialize(params.input) {
+        Ok(input) => input,
+        Err(err) => {
+            send_error(err);
+            return;
+        }
+    };
+
+    let errors = rpc.call_errors(procedure, input.as_ref());
+    let call = rpc.call(procedure, input, state.clone(), parts);
+    let task = tokio::spawn(async move {
+        let response = call.await;
+        let mut events = match response.into_events() {
+            Ok(events) => events,
+            Err(response) => {
+                responder.send_response(response);
+                return;
+            }
+        };
+
+        responder.send_result(json!({ "type": "started" }));
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(event) => responder.send_data(event.data, &event.annotations, event.id),
+                Err(err) => {
+                    responder.send_response(errors.response(err));
+                    return;
+                }
+            }
+        }
+
+        responder.send_result(json!({ "type": "stopped" }));
+    });
+
+    if rpc_type == RpcType::Subscription {
+        // Subscriptions that ended on their own are never stopped, so they are dropped here
+        subscriptions.retain(|_, subscription| !subscription.is_finished());
+        subscriptions.insert(subscription_key, task);
+    }
+}
+
+/// `wsLink` expects `{ result: { type: "data", data } }` where HTTP has `{ result: { data } }`.
+fn with_data_type(mut envelope: Value) -> Value {
+    if let Some(Value::Object(result)) = envelope.get_mut("result") {
+        result.insert("type".to_string(), json!("data"));
+    }
+    envelope
+}
@@ -0,0 +1,539 @@
+use crate::validation::Rule;
+use specta::{
+    TypeCollection,
+    datatype::{
+        DataType, EnumRepr, EnumVariants, Field, LiteralType, NamedDataType, PrimitiveType,
+        StructFields,
+    },
+};
+use std::collections::HashMap;
+
+/// Turns specta types into zod schemas, for the `schemas.ts` written next to the client.
+pub(crate) struct ZodExporter<'a> {
+    types: &'a TypeCollection,
+    /// Whether 64-bit and larger integers are sent as `bigint`, as with superjson.
+    bigint: bool,
+    /// The generics of the named type being exported, which are the parameters of its schema.
+    generics: Vec<String>,
+}
+
+impl<'a> ZodExporter<'a> {
+    pub(crate) fn new(types: &'a TypeCollection, bigint: bool) -> Self {
+        Self {
+            types,
+            bigint,
+            generics: Vec::new(),
+        }
+    }
+
+    /// A `{Name}Schema` constant for every named type. Types with generics become functions
+    /// taking the schemas of their generics.
+    ///
+    /// `rules` holds the `#[validate(..)]` rules for the fields of named input types.
+    pub(crate) fn export(&self, rules: &HashMap<&str, &[(&str, Rule)]>) -> String {
+        let mut types: Vec<&NamedDataType> = self.types.into_iter().map(|(_, ty)| ty).collect();
+        types.sort_by(|a, b| a.name().cmp(b.name()));
+
+        types
+            .into_iter()
+            .map(|ty| {
+                let name = ty.name();
+                let rules = rules.get(name.as_ref()).copied().unwrap_or_default();
+                let generics = ty.inner.generics().cloned().unwrap_or_default();
+                let scoped = Self {
+                    generics: generics.iter().map(ToString::to_string).collect(),
+                    ..*self
+                };
+                let schema = scoped.datatype_with_rules(&ty.inner, rules);
+                let docs = jsdoc(ty.docs());
+                if generics.is_empty() {
+                    return format!("{docs}export const {name}Schema = {schema};");
+                }
+
+                let params = generics
+                    .iter()
+                    .map(|generic| format!("{generic}: {generic}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let bounds = generics
+                    .iter()
+                    .map(|generic| format!("{generic} extends z.ZodTypeAny"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{docs}export const {name}Schema = <{bounds}>({params}) => {schema};")
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    pub(crate) fn datatype(&self, ty: &DataType) -> String {
+        self.datatype_with_rules(ty, &[])
+    }
+
+    /// The schema for `ty`, applying `rules` to its fields if it is a struct.
+    pub(crate) fn datatype_with_rules(&self, ty: &DataType, rules: &[(&str, Rule)]) -> String {
+        match ty {
+            DataType::Any => "z.any()".to_string(),
+            DataType::Unknown => "z.unknown()".to_string(),
+            DataType::Primitive(primitive) => self.primitive(primitive),
+            DataType::Literal(literal) => literal_schema(literal),
+            DataType::List(list) => {
+                let schema = format!("z.array({})", self.datatype(list.ty()));
+                match list.length() {
+                    Some(length) => format!("{schema}.length({length})"),
+                    None => schema,
+                }
+            }
+            // JSON object keys are always strings
+            DataType::Map(map) => {
+                format!("z.record(z.string(), {})", self.datatype(map.value_ty()))
+            }
+            DataType::Nullable(inner) => format!("{}.nullable()", self.datatype(inner)),
+            DataType::Struct(ty) => {
+                let tag = ty.tag().map(|tag| (tag.as_ref(), ty.name().as_ref()));
+                match ty.fields() {
+                    StructFields::Unit => "z.null()".to_string(),
+                    StructFields::Unnamed(fields) => self.unnamed(fields.fields()),
+                    StructFields::Named(fields) => self.object(fields.fields(), tag, rules),
+                }
+            }
+            DataType::Enum(ty) => {
+                let variants: Vec<_> = ty
+                    .variants()
+                    .iter()
+                    .filter(|(_, variant)| !variant.skip())
+                    .map(|(name, variant)| self.variant(ty.repr(), name, variant.inner()))
+                    .collect();
+                match variants.as_slice() {
+                    [] => "z.never()".to_string(),
+                    [variant] => variant.clone(),
+                    variants => format!("z.union([{}])", variants.join(", ")),
+                }
+            }
+            DataType::Tuple(tuple) => match tuple.elements().as_slice() {
+                // `()` is sent as `null`
+                [] => "z.null()".to_string(),
+                elements => format!(
+                    "z.tuple([{}])",
+                    elements
+                        .iter()
+                        .map(|element| self.datatype(element))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            // Schemas are defined in name order, so references are resolved lazily
+            DataType::Reference(reference) => {
+                let generics = reference
+                    .generics()
+                    .iter()
+                    .map(|(_, ty)| self.datatype(ty))
+                    .collect::<Vec<_>>();
+                let name = match self.types.get(reference.sid()) {
+                    Some(named) => named.name().clone(),
+                    None => reference.name().clone(),
+                };
+                if generics.is_empty() {
+                    format!("z.lazy(() => {name}Schema)")
+                } else {
+                    format!("z.lazy(() => {name}Schema({}))", generics.join(", "))
+                }
+            }
+            DataType::Generic(generic) if self.generics.contains(&generic.to_string()) => {
+                generic.to_string()
+            }
+            // Types only the client knows, like the `FormData` of uploads
+            DataType::Generic(generic) => format!("z.custom<{generic}>()"),
+        }
+    }
+
+    fn primitive(&self, primitive: &PrimitiveType) -> String {
+        use PrimitiveType::*;
+
+        match primitive {
+            i64 | u64 | i128 | u128 | isize | usize if self.bigint => "z.bigint()".to_string(),
+            i8 | i16 | i32 | i64 | i128 | isize => "z.number().int()".to_string(),
+            u8 | u16 | u32 | u64 | u128 | usize => "z.number().int().nonnegative()".to_string(),
+            f32 | f64 => "z.number()".to_string(),
+            bool => "z.boolean()".to_string(),
+            char => "z.string().length(1)".to_string(),
+            String => "z.string()".to_string(),
+        }
+    }
+
+    /// Tuple structs and variants, where a single field is sent as just that field.
+    fn unnamed(&self, fields: &[Field]) -> String {
+        let fields: Vec<_> = fields
+            .iter()
+            .filter_map(|field| field.ty().map(|ty| self.field(field, ty, &[])))
+            .collect();
+        match fields.as_slice() {
+            [field] => field.clone(),
+            fields => format!("z.tuple([{}])", fields.join(", ")),
+        }
+    }
+
+    /// The schema for a procedure's input. Rules for the field `""`, from an `#[input]`
+    /// argument, apply to the whole input.
+    pub(crate) fn input(&self, ty: &DataType, rules: &[(&str, Rule)]) -> String {
+        let input_rules: Vec<_> = rules
+            .iter()
+            .filter(|(field, _)| field.is_empty())
+            .map(|(_, rule)| *rule)
+            .collect();
+        match ty {
+            DataType::Nullable(inner) if !input_rules.is_empty() => format!(
+                "{}.nullable()",
+                apply_rules(self.datatype(inner), inner, &input_rules)
+            ),
+            ty => apply_rules(self.datatype_with_rules(ty, rules), ty, &input_rules),
+        }
+    }
+
+    fn object(
+        &self,
+        fields: &[(std::borrow::Cow<'static, str>, Field)],
+        tag: Option<(&str, &str)>,
+        rules: &[(&str, Rule)],
+    ) -> String {
+        let mut entries = Vec::new();
+        if let Some((tag, value)) = tag {
+            entries.push(format!("{}: z.literal({})", key(tag), string(value)));
+        }
+
+        let mut flattened = Vec::new();
+        for (name, field) in fields {
+            let Some(ty) = field.ty() else {
+                continue;
+            };
+            let field_rules: Vec<_> = rules
+                .iter()
+                .filter(|(field, _)| field == name)
+                .map(|(_, rule)| *rule)
+                .collect();
+            let schema = self.field(field, ty, &field_rules);
+            if field.flatten() {
+                flattened.push(schema);
+            } else {
+                entries.push(format!("{}: {}", key(name), schema));
+            }
+        }
+
+        let object = format!("z.object({{ {} }})", entries.join(", "));
+        flattened.into_iter().fold(object, |object, flattened| {
+            format!("{object}.and({flattened})")
+        })
+    }
+
+    fn field(&self, field: &Field, ty: &DataType, rules: &[Rule]) -> String {
+        // Rules apply to the value itself, not the `null` an `Option` may also be
+        let mut schema = match ty {
+            DataType::Nullable(inner) => {
+                format!(
+                    "{}.nullable()",
+                    apply_rules(self.datatype(inner), inner, rules)
+                )
+            }
+            ty => apply_rules(self.datatype(ty), ty, rules),
+        };
+        if field.optional() {
+            schema.push_str(".optional()");
+        }
+        if !field.docs().is_empty() {
+            schema.push_str(&format!(".describe({})", string(field.docs().trim())));
+        }
+        schema
+    }
+
+    fn variant(&self, repr: &EnumRepr, name: &str, variant: &EnumVariants) -> String {
+        let content = match variant {
+            EnumVariants::Unit => None,
+            EnumVariants::Named(fields) => Some(self.object(fields.fields(), None, &[])),
+            EnumVariants::Unnamed(fields) => Some(self.unnamed(fields.fields())),
+        };
+
+        match (repr, content) {
+            (EnumRepr::External, None) => format!("z.literal({})", string(name)),
+            (EnumRepr::External, Some(content)) => {
+                format!("z.object({{ {}: {content} }})", key(name))
+            }
+            (EnumRepr::Untagged, None) => "z.null()".to_string(),
+            (EnumRepr::Untagged, Some(content)) => content,
+            (EnumRepr::Internal { tag }, None) => {
+                format!("z.object({{ {}: z.literal({}) }})", key(tag), string(name))
+            }
+            (EnumRepr::Internal { tag }, Some(content)) => format!(
+                "z.object({{ {}: z.literal({}) }}).and({content})",
+                key(tag),
+                string(name)
+            ),
+            (EnumRepr::Adjacent { tag, .. }, None) => {
+                format!("z.object({{ {}: z.literal({}) }})", key(tag), string(name))
+            }
+            (
+                EnumRepr::Adjacent {
+                    tag,
+                    content: content_key,
+                },
+                Some(content),
+            ) => format!(
+                "z.object({{ {}: z.literal({}), {}: {content} }})",
+                key(tag),
+                string(name),
+                key(content_key)
+            ),
+        }
+    }
+}
+
+/// Adds the checks for `#[validate(..)]` rules to a field's schema. Rules that don't fit the
+/// field's type are left to the server.
+fn apply_rules(mut schema: String, ty: &DataType, rules: &[Rule]) -> String {
+    let is_string = matches!(ty, DataType::Primitive(PrimitiveType::String));
+    let is_number = matches!(ty, DataType::Primitive(primitive) if !matches!(
+        primitive,
+        PrimitiveType::bool | PrimitiveType::char | PrimitiveType::String
+    ));
+    let is_bigint = schema.starts_with("z.bigint()");
+
+    for rule in rules {
+        match *rule {
+            Rule::Length { min, max } if is_string || matches!(ty, DataType::List(_)) => {
+                if let Some(min) = min {
+                    schema.push_str(&format!(".min({min})"));
+                }
+                if let Some(max) = max {
+                    schema.push_str(&format!(".max({max})"));
+                }
+            }
+            Rule::Range { min, max } if is_number => {
+                // The macro only accepts bounds of the field's own type, so a bigint field's
+                // bounds are whole numbers
+                let bound = |bound: f64| match is_bigint {
+                    true => format!("{}n", bound as i128),
+                    false => bound.to_string(),
+                };
+                if let Some(min) = min {
+                    schema.push_str(&format!(".gte({})", bound(min)));
+                }
+                if let Some(max) = max {
+                    schema.push_str(&format!(".lte({})", bound(max)));
+                }
+            }
+            Rule::Email if is_string => schema.push_str(".email()"),
+            _ => {}
+        }
+    }
+    schema
+}
+
+fn literal_schema(literal: &LiteralType) -> String {
+    let value = match literal {
+        LiteralType::i8(value) => value.to_string(),
+        LiteralType::i16(value) => value.to_string(),
+        LiteralType::i32(value) => value.to_string(),
+        LiteralType::u8(value) => value.to_string(),
+        LiteralType::u16(value) => value.to_string(),
+        LiteralType::u32(value) => value.to_string(),
+        LiteralType::f32(value) => value.to_string(),
+        LiteralType::f64(value) => value.to_string(),
+        LiteralType::bool(value) => value.to_string(),
+        LiteralType::String(value) => string(value),
+        LiteralType::char(value) => string(&value.to_string()),
+        LiteralType::None => return "z.null()".to_string(),
+        _ => return "z.unknown()".to_string(),
+    };
+    format!("z.literal({value})")
+}
+
+/// An object key, quoted unless it is a valid identifier.
+pub(crate) fn key(name: &str) -> String {
+    let is_ident = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_ident {
+        name.to_string()
+    } else {
+        string(name)
+    }
+}
+
+fn string(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn jsdoc(docs: &str) -> String {
+    let docs = docs.trim();
+    if docs.is_empty() {
+        return String::new();
+    }
+
+    let lines: Vec<_> = docs
+        .lines()
+        .map(|line| format!(" * {}", line.trim()))
+        .collect();
+    format!("/**\n{}\n */\n", lines.join("\n"))
+}
+
+#[cfg(test)]
+// The types are only exported, never constructed
+#[allow(dead_code)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use specta::Type;
+
+    #[derive(Serialize, Type)]
+    enum External {
+        Unit,
+        Named { id: u32 },
+        Unnamed(String),
+    }
+
+    #[derive(Serialize, Type)]
+    #[serde(tag = "type")]
+    enum Internal {
+        Unit,
+        Named { id: u32 },
+    }
+
+    #[derive(Serialize, Type)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Unit,
+        Unnamed(String),
+    }
+
+    #[derive(Serialize, Type)]
+    #[serde(untagged)]
+    enum Untagged {
+        Named { id: u32 },
+        Unnamed(String),
+    }
+
+    #[derive(Serialize, Type)]
+    struct Base {
+        id: u64,
+    }
+
+    #[derive(Serialize, Type)]
+    struct Flattened {
+        #[serde(flatten)]
+        base: Base,
+        name: String,
+    }
+
+    #[derive(Serialize, Type)]
+    struct Page<T> {
+        items: Vec<T>,
+        next: Option<String>,
+    }
+
+    #[derive(Serialize, Type)]
+    struct Users {
+        page: Page<Base>,
+    }
+
+    #[derive(Serialize, Type)]
+    struct Invite {
+        email: String,
+        nick: Option<String>,
+        seats: u64,
+    }
+
+    fn export<T: specta::NamedType>(bigint: bool, rules: &[(&str, Rule)]) -> String {
+        let mut types = TypeCollection::default();
+        types.register::<T>();
+        let name = T::definition_named_data_type(&mut types).name().clone();
+        let rules = HashMap::from([(name.as_ref(), rules)]);
+        ZodExporter::new(&types, bigint).export(&rules)
+    }
+
+    #[test]
+    fn enum_representations() {
+        assert_eq!(
+            export::<External>(false, &[]),
+            r#"export const ExternalSchema = z.union([z.literal("Unit"), z.object({ Named: z.object({ id: z.number().int().nonnegative() }) }), z.object({ Unnamed: z.string() })]);"#
+        );
+        assert_eq!(
+            export::<Internal>(false, &[]),
+            r#"export const InternalSchema = z.union([z.object({ type: z.literal("Unit") }), z.object({ type: z.literal("Named") }).and(z.object({ id: z.number().int().nonnegative() }))]);"#
+        );
+        assert_eq!(
+            export::<Adjacent>(false, &[]),
+            r#"export const AdjacentSchema = z.union([z.object({ t: z.literal("Unit") }), z.object({ t: z.literal("Unnamed"), c: z.string() })]);"#
+        );
+        assert_eq!(
+            export::<Untagged>(false, &[]),
+            r#"export const UntaggedSchema = z.union([z.object({ id: z.number().int().nonnegative() }), z.string()]);"#
+        );
+    }
+
+    #[test]
+    fn flattened_fields_are_intersected() {
+        assert_eq!(
+            export::<Flattened>(false, &[]),
+            r#"export const FlattenedSchema = z.object({ name: z.string() }).and(z.object({ id: z.number().int().nonnegative() }));"#
+        );
+    }
+
+    #[test]
+    fn generic_types_take_schemas() {
+        let exported = export::<Users>(false, &[]);
+        assert!(exported.contains(
+            "export const PageSchema = <T extends z.ZodTypeAny>(T: T) => z.object({ items: z.array(T), next: z.string().nullable() });"
+        ));
+        assert!(exported.contains(
+            "export const UsersSchema = z.object({ page: z.lazy(() => PageSchema(z.lazy(() => BaseSchema))) });"
+        ));
+    }
+
+    #[test]
+    fn rules_apply_to_matching_fields() {
+        let rules = [
+            ("email", Rule::Email),
+            (
+                "nick",
+                Rule::Length {
+                    min: Some(1),
+                    max: Some(32),
+                },
+            ),
+            (
+                "seats",
+                Rule::Range {
+                    min: Some(2.0),
+                    max: Some(10.0),
+                },
+            ),
+            // Left to the server, as it doesn't fit the field's type
+            (
+                "email",
+                Rule::Range {
+                    min: Some(1.0),
+                    max: None,
+                },
+            ),
+        ];
+        assert_eq!(
+            export::<Invite>(false, &rules),
+            r#"export const InviteSchema = z.object({ email: z.string().email(), nick: z.string().min(1).max(32).nullable(), seats: z.number().int().nonnegative().gte(2).lte(10) });"#
+        );
+    }
+
+    #[test]
+    fn bigint_bounds_are_bigint_literals() {
+        let rules = [(
+            "seats",
+            Rule::Range {
+                min: Some(2.0),
+                max: Some(10.0),
+            },
+        )];
+        assert!(export::<Invite>(true, &rules).contains("seats: z.bigint().gte(2n).lte(10n)"));
+    }
+}